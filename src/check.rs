@@ -0,0 +1,389 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::config::Entry;
+use crate::git;
+use crate::walker::EntrySelector;
+
+/// How serious a [`Finding`] is. Both make `wtl check` fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config cannot be used as written.
+    Error,
+    /// The config works, but a pattern probably doesn't do what was meant.
+    Warning,
+}
+
+/// A problem with a single config entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Index into the checked entries.
+    pub entry: usize,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(entry: usize, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Error,
+            entry,
+            message: message.into(),
+        }
+    }
+
+    fn warning(entry: usize, message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Warning,
+            entry,
+            message: message.into(),
+        }
+    }
+
+    /// Format the finding for terminal output, with paths shown relative to
+    /// `source` where possible.
+    pub fn display(&self, entries: &[Entry], source: &Path) -> String {
+        let entry = &entries[self.entry];
+        let label = match self.severity {
+            Severity::Error => "ERROR".red().bold(),
+            Severity::Warning => "WARN".yellow().bold(),
+        };
+        let location = match &entry.origin {
+            Some(origin) => {
+                let mut origin = origin.clone();
+                if let Ok(rel) = origin.file.strip_prefix(source) {
+                    origin.file = rel.to_path_buf();
+                }
+                format!("{origin}: ")
+            }
+            None => String::new(),
+        };
+        let expanded = match &entry.unexpanded {
+            Some(written) => format!(" (from `{written}`)"),
+            None => String::new(),
+        };
+        format!(
+            "{label} {location}`{}`{expanded}: {}",
+            entry.pattern, self.message
+        )
+    }
+}
+
+/// Lint `entries` against the `source` tree.
+///
+/// Reports patterns that are invalid globs, that escape the source directory,
+/// that match nothing, whose every match is excluded by a later negation, and
+/// that select files tracked by git (which would clash with the checkout).
+/// Only the entries marked in `selected` (e.g. those of the selected groups)
+/// are matched against the tree; the others are only checked for errors.
+pub fn check(source: &Path, entries: &[Entry], selected: &[bool]) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    // Patterns that fail here are left out of every later check.
+    let mut matchers: Vec<Option<Gitignore>> = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        if escapes_root(&entry.pattern) {
+            findings.push(Finding::error(i, "escapes the source directory"));
+            matchers.push(None);
+            continue;
+        }
+        // The linker goes through `Override`, which is stricter about globs
+        // than `Gitignore`, so validate with both.
+        let built = OverrideBuilder::new(source)
+            .add(&entry.pattern)
+            .map(|_| ())
+            .and_then(|()| {
+                let mut builder = GitignoreBuilder::new(source);
+                builder.add_line(None, &entry.pattern)?;
+                builder.build()
+            });
+        match built {
+            Ok(matcher) => matchers.push(Some(matcher)),
+            Err(e) => {
+                findings.push(Finding::error(i, format!("invalid glob: {e}")));
+                matchers.push(None);
+            }
+        }
+    }
+
+    // From here on, unselected entries are treated like invalid ones.
+    for (matcher, &selected) in matchers.iter_mut().zip(selected) {
+        if !selected {
+            *matcher = None;
+        }
+    }
+
+    let valid: Vec<Entry> = entries
+        .iter()
+        .zip(&matchers)
+        .filter(|(_, m)| m.is_some())
+        .map(|(e, _)| e.clone())
+        .collect();
+    let selector = EntrySelector::new(source, &valid)?;
+
+    let mut hits = vec![0usize; entries.len()];
+    let mut first_excluded: Vec<Option<(PathBuf, bool)>> = vec![None; entries.len()];
+    let mut survives = vec![false; entries.len()];
+
+    for (path, is_dir) in walk_source(source, &selector, &valid)? {
+        let excluded = selector.is_excluded(&path, is_dir);
+        for (i, matcher) in matchers.iter().enumerate() {
+            let Some(matcher) = matcher else { continue };
+            // Negations report their matches as whitelisted.
+            if matcher.matched(&path, is_dir).is_none() {
+                continue;
+            }
+            hits[i] += 1;
+            if !excluded {
+                survives[i] = true;
+            } else if first_excluded[i].is_none() {
+                first_excluded[i] = Some((path.clone(), is_dir));
+            }
+        }
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if matchers[i].is_none() {
+            continue;
+        }
+        if hits[i] == 0 {
+            // Entries with an `@source` may still match in that tree.
+            if entry.source.is_none() {
+                findings.push(Finding::warning(i, "matches nothing in the source"));
+            }
+            continue;
+        }
+        let negated = entry.pattern.starts_with('!');
+        if negated || survives[i] {
+            continue;
+        }
+        if let Some((path, is_dir)) = &first_excluded[i] {
+            let by = last_negation(&matchers, entries, i, path, *is_dir)
+                .map(|j| format!(" `{}`", entries[j].pattern))
+                .unwrap_or_default();
+            findings.push(Finding::warning(
+                i,
+                format!("every match is excluded by a later negation{by}"),
+            ));
+        }
+    }
+
+    findings.extend(check_tracked(source, entries, &matchers, &selector));
+
+    findings.sort_by_key(|f| f.entry);
+    Ok(findings)
+}
+
+/// Whether a pattern refers to something outside the directory it applies to.
+fn escapes_root(pattern: &str) -> bool {
+    let pattern = pattern.trim_start_matches('!');
+    Path::new(pattern)
+        .components()
+        .any(|c| matches!(c, Component::ParentDir))
+}
+
+/// The last negation after entry `i` that matches `path`.
+fn last_negation(
+    matchers: &[Option<Gitignore>],
+    entries: &[Entry],
+    i: usize,
+    path: &Path,
+    is_dir: bool,
+) -> Option<usize> {
+    (i + 1..entries.len()).rev().find(|&j| {
+        entries[j].pattern.starts_with('!')
+            && matchers[j]
+                .as_ref()
+                .is_some_and(|m| m.matched(path, is_dir).is_whitelist())
+    })
+}
+
+/// Every path the linker could consider: the whole source tree except `.git`,
+/// without descending into directories that would be linked as a whole.
+/// `.gitignore` is not applied, since patterns are meant to select ignored
+/// files.
+fn walk_source(
+    source: &Path,
+    selector: &EntrySelector,
+    entries: &[Entry],
+) -> Result<Vec<(PathBuf, bool)>> {
+    let linked_dirs = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let filter_dirs = Arc::clone(&linked_dirs);
+    let deep: Vec<bool> = entries.iter().map(|e| e.deep).collect();
+    let selector = Arc::new(selector.clone());
+    let filter_selector = Arc::clone(&selector);
+    let root = source.to_path_buf();
+
+    let walker = WalkBuilder::new(source)
+        .standard_filters(false)
+        .filter_entry(move |entry| {
+            if entry.file_name() == ".git" {
+                return false;
+            }
+            let path = entry.path();
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            if path == root || !is_dir {
+                return true;
+            }
+            match filter_selector.select(path, true) {
+                Some(owner) if !deep[owner] => {
+                    filter_dirs.lock().unwrap().push(path.to_path_buf());
+                    false
+                }
+                _ => true,
+            }
+        })
+        .build();
+
+    let mut paths = Vec::new();
+    for entry in walker {
+        let entry = entry.with_context(|| "Error walking directory")?;
+        if entry.path() == source {
+            continue;
+        }
+        let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+        paths.push((entry.into_path(), is_dir));
+    }
+    paths.extend(linked_dirs.lock().unwrap().drain(..).map(|p| (p, true)));
+    Ok(paths)
+}
+
+/// Warn about positive patterns that select files tracked by git.
+fn check_tracked(
+    source: &Path,
+    entries: &[Entry],
+    matchers: &[Option<Gitignore>],
+    selector: &EntrySelector,
+) -> Vec<Finding> {
+    let tracked = match git::ls_files(source) {
+        Ok(tracked) => tracked,
+        Err(e) => {
+            warn!("Skipping tracked-file check: {e:#}");
+            return Vec::new();
+        }
+    };
+
+    let mut findings = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(matcher) = &matchers[i] else {
+            continue;
+        };
+        if entry.pattern.starts_with('!') {
+            continue;
+        }
+        let matched: Vec<&PathBuf> = tracked
+            .iter()
+            .filter(|rel| {
+                let path = source.join(rel);
+                matcher
+                    .matched_path_or_any_parents(&path, false)
+                    .is_ignore()
+                    && !selector.is_excluded_or_parents(&path)
+            })
+            .collect();
+        if let Some(first) = matched.first() {
+            let more = match matched.len() {
+                1 => String::new(),
+                n => format!(" and {} more", n - 1),
+            };
+            findings.push(Finding::warning(
+                i,
+                format!(
+                    "matches tracked file {}{more}; linking it would fight with git",
+                    first.display()
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process::Command;
+
+    fn entries(lines: &[&str]) -> Vec<Entry> {
+        lines.iter().map(|&l| l.into()).collect()
+    }
+
+    fn messages(findings: &[Finding]) -> Vec<(usize, Severity, &str)> {
+        findings
+            .iter()
+            .map(|f| (f.entry, f.severity, f.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn check_reports_each_kind_of_problem() {
+        let dir = std::env::temp_dir().join("worktree-link-test-check");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("node_modules/pkg")).unwrap();
+        fs::write(dir.join("node_modules/pkg/index.js"), "").unwrap();
+        fs::write(dir.join(".env"), "").unwrap();
+        fs::write(dir.join(".env.prod"), "").unwrap();
+        fs::write(dir.join("Cargo.toml"), "").unwrap();
+        for args in [&["init", "--quiet"][..], &["add", "Cargo.toml"]] {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(&dir)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        let config = entries(&[
+            "node_modules",
+            "dist/",
+            ".env.prod",
+            "a/[",
+            "../shared",
+            "!.env.prod",
+            "Cargo.toml",
+        ]);
+        let findings = check(&dir, &config, &[true; 7]).unwrap();
+        let found = messages(&findings);
+        assert_eq!(found.len(), 5, "{found:?}");
+        assert_eq!(
+            found[0],
+            (1, Severity::Warning, "matches nothing in the source")
+        );
+        assert_eq!(
+            found[1],
+            (
+                2,
+                Severity::Warning,
+                "every match is excluded by a later negation `!.env.prod`"
+            )
+        );
+        assert_eq!(found[2].0, 3);
+        assert_eq!(found[2].1, Severity::Error);
+        assert!(found[2].2.starts_with("invalid glob"));
+        assert_eq!(
+            found[3],
+            (4, Severity::Error, "escapes the source directory")
+        );
+        assert_eq!(found[4].0, 6);
+        assert!(found[4].2.starts_with("matches tracked file Cargo.toml"));
+
+        // Unselected entries are only checked for errors.
+        let config = entries(&["dist/", "a/[", "../shared", "!.env.prod", ".env.prod"]);
+        let findings = check(&dir, &config, &[false; 5]).unwrap();
+        let found = messages(&findings);
+        assert_eq!(found.len(), 2, "{found:?}");
+        assert_eq!((found[0].0, found[0].1), (1, Severity::Error));
+        assert_eq!(
+            found[1],
+            (2, Severity::Error, "escapes the source directory")
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,42 +1,164 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+const CONFIG_HELP: &str = "\
+Config files (later files take precedence; their `!` negations cancel earlier patterns):
+  1. <CONFIG> and <CONFIG>.d/*.conf      shared project config
+  2. $XDG_CONFIG_HOME/worktree-link/worktreelinks
+                                         personal patterns for every repository
+  3. <CONFIG>.local                      personal, uncommitted project config
+  4. <SUBDIR>/.worktreelinks             nested configs, relative to their directory
+                                         (with `%nested`; parents before children)
+  5. git config worktree-link.pattern    read in the target worktree (also .force, .relative)
+  6. --pattern / --exclude               apply regardless of the selected groups
+
+Defaults for the options above can be set in $XDG_CONFIG_HOME/worktree-link/config.toml
+(e.g. `force = true`, `strategy = \"reflink,copy\"`, `patterns = [\".env\"]` for repositories
+without a config). Command-line options take precedence.";
+
 /// Automatically create symlinks between git worktrees based on .worktreelinks patterns.
 #[derive(Parser, Debug)]
-#[command(name = "worktree-link", version, about)]
+#[command(name = "worktree-link", version, about, after_help = CONFIG_HELP)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Source directory (main worktree).
     /// Auto-detected via `git worktree list` if omitted.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub source: Option<PathBuf>,
 
-    /// Target directory (new worktree)
-    #[arg(short, long, default_value = ".")]
-    pub target: PathBuf,
+    /// Target directory (new worktree) [default: .]
+    #[arg(short, long, global = true)]
+    pub target: Option<PathBuf>,
+
+    /// Path to config file; repeat to merge several in order, `-` reads
+    /// one from stdin
+    /// [default: <SOURCE>/.worktreelinks.toml if present, else <SOURCE>/.worktreelinks]
+    #[arg(short, long = "config", global = true)]
+    pub config: Vec<PathBuf>,
 
-    /// Path to config file [default: <SOURCE>/.worktreelinks]
-    #[arg(short, long = "config")]
-    pub config: Option<PathBuf>,
+    /// Add a pattern (in config file syntax) after the config files;
+    /// can be repeated
+    #[arg(short, long = "pattern", value_name = "PATTERN", global = true)]
+    pub patterns: Vec<String>,
+
+    /// Exclude paths matching PATTERN, like a `!PATTERN` line after the
+    /// config files; can be repeated
+    #[arg(long = "exclude", value_name = "PATTERN", global = true)]
+    pub excludes: Vec<String>,
+
+    /// Use only the --pattern/--exclude patterns, ignoring the patterns in
+    /// config files (their options still apply)
+    #[arg(long, requires = "patterns", global = true)]
+    pub only_cli_patterns: bool,
 
     /// Show what would be done without making changes
-    #[arg(short = 'n', long)]
+    #[arg(short = 'n', long, global = true, overrides_with = "no_dry_run")]
     pub dry_run: bool,
 
+    /// Make changes even if the user config enables --dry-run
+    #[arg(long, global = true, overrides_with = "dry_run")]
+    pub no_dry_run: bool,
+
     /// Overwrite existing files/symlinks
-    #[arg(short, long)]
+    #[arg(short, long, global = true, overrides_with = "no_force")]
     pub force: bool,
 
+    /// Don't overwrite, even if the user config or the config file enables force
+    #[arg(long, global = true, overrides_with = "force")]
+    pub no_force: bool,
+
     /// Enable verbose logging
-    #[arg(short, long)]
+    #[arg(short, long, global = true, overrides_with = "no_verbose")]
     pub verbose: bool,
 
+    /// Disable verbose logging enabled in the user config
+    #[arg(long, global = true, overrides_with = "verbose")]
+    pub no_verbose: bool,
+
+    /// Ignore the user config file and the personal patterns file under
+    /// $XDG_CONFIG_HOME/worktree-link/
+    #[arg(long, global = true)]
+    pub no_user_config: bool,
+
+    /// Create relative symlinks instead of absolute ones.
+    /// Can also be enabled with a `%relative` line in the config file.
+    #[arg(long, global = true, overrides_with = "no_relative")]
+    pub relative: bool,
+
+    /// Create absolute symlinks, even if the user config or the config file
+    /// enables relative ones
+    #[arg(long, global = true, overrides_with = "relative")]
+    pub no_relative: bool,
+
     /// Remove symlinks previously created by worktree-link
     #[arg(long)]
     pub unlink: bool,
 
+    /// With --unlink, replace links with independent copies of their
+    /// content instead of removing them (same as the `detach` command)
+    #[arg(long, requires = "unlink")]
+    pub materialize: bool,
+
+    /// Use the groups of a profile defined with `%profile <NAME> <GROUP>...`
+    /// (together with any --only groups)
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Only use patterns from these `[group "..."]` sections
+    /// (ungrouped patterns are in `default`)
+    #[arg(long, value_name = "GROUP", value_delimiter = ',', global = true)]
+    pub only: Vec<String>,
+
+    /// Ignore patterns from these `[group "..."]` sections
+    #[arg(long, value_name = "GROUP", value_delimiter = ',', global = true)]
+    pub skip: Vec<String>,
+
     /// Don't respect .gitignore rules.
     /// By default, files matched by .gitignore are excluded unless
     /// they also match a .worktreelinks pattern.
-    #[arg(long)]
+    #[arg(long, global = true, overrides_with = "respect_ignore")]
     pub no_ignore: bool,
+
+    /// Respect .gitignore rules, even if the user config sets `no-ignore`
+    #[arg(long = "ignore", global = true, overrides_with = "no_ignore")]
+    pub respect_ignore: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print `export` lines with per-worktree values for the target worktree
+    /// (slot, port offset, branch, ...). Use with `eval "$(wtl env)"`.
+    Env,
+
+    /// Move files/directories from the target worktree into the same place in
+    /// the source and replace them with symlinks. Refuses to replace existing
+    /// source entries unless --force is given.
+    Adopt {
+        /// Paths inside the target worktree to adopt
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Also append a matching pattern to the config file
+        #[arg(long)]
+        add: bool,
+    },
+
+    /// Lint the config: invalid globs, patterns that match nothing, are
+    /// shadowed by later negations, select tracked files, or escape the
+    /// source directory. Exits non-zero if anything is found.
+    Check,
+
+    /// Write a commented config listing the gitignored entries present in
+    /// the source, with known dependency and build directories enabled.
+    /// Refuses to overwrite an existing config unless --force is given.
+    Init,
+
+    /// Replace links into the source with independent copies of their
+    /// content, e.g. before deleting the main worktree.
+    Detach {
+        /// Only detach links at or below these paths [default: all]
+        paths: Vec<PathBuf>,
+    },
 }
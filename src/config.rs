@@ -1,39 +1,1089 @@
-use anyhow::{Context, Result};
-use std::path::Path;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-/// Parsed configuration from a `.worktreelinks` file.
-#[derive(Debug)]
+use tracing::{debug, warn};
+
+use crate::git;
+use crate::linker::{OnConflict, Strategy};
+
+/// The `version` of the `.worktreelinks.toml` schema this build understands.
+const TOML_VERSION: u32 = 1;
+
+/// Group of entries that appear before any `[group "name"]` section header.
+pub const DEFAULT_GROUP: &str = "default";
+
+/// Config path (`-c -`) that reads the config from standard input.
+pub const STDIN: &str = "-";
+
+/// Parsed configuration from a `.worktreelinks` or `.worktreelinks.toml` file.
+#[derive(Debug, Clone, Default)]
 pub struct Config {
-    /// Glob patterns that select files/directories to link.
+    /// Entries in file order. Later entries take precedence when several match.
+    pub entries: Vec<Entry>,
+    /// Create relative instead of absolute symlinks (`%relative`).
+    pub relative: bool,
+    /// Also read config files in subdirectories of the source (`%nested`).
+    pub nested: bool,
+    /// Overwrite existing entries (only settable via `worktree-link.force`
+    /// in git config).
+    pub force: bool,
+    /// Named sets of groups selectable with `--profile` (`%profile`).
+    pub profiles: BTreeMap<String, Vec<String>>,
+    /// Additional trees declared with `@source <name> <path>`, searched for
+    /// entries with a `<name>:` prefix.
+    pub sources: BTreeMap<String, PathBuf>,
+}
+
+/// The section header state that applies to the entries below it.
+#[derive(Debug, Clone)]
+struct Section {
+    group: String,
+    branch: Option<String>,
+    /// Environment variables are expanded in lines (`%expand`).
+    expand: bool,
+    /// Unset variables are an error rather than empty (`%strict`).
+    strict: bool,
+}
+
+impl Default for Section {
+    fn default() -> Self {
+        Section {
+            group: DEFAULT_GROUP.to_string(),
+            branch: None,
+            expand: false,
+            strict: false,
+        }
+    }
+}
+
+/// A section header line.
+enum Header<'a> {
+    /// `[group "name"]`: start a group (and end any branch condition).
+    Group(&'a str),
+    /// `[branch "glob"]`: make the following entries conditional.
+    Branch(&'a str),
+}
+
+/// Patterns given on the command line with `--pattern` and `--exclude`.
+#[derive(Debug, Clone, Default)]
+pub struct ExtraPatterns {
     pub patterns: Vec<String>,
+    /// Each is added as a `!` negation.
+    pub excludes: Vec<String>,
+    /// Use only these, dropping the entries from config files
+    /// (`--only-cli-patterns`).
+    pub replace: bool,
+}
+
+impl ExtraPatterns {
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.excludes.is_empty()
+    }
+}
+
+/// Which groups of entries to use, from `--profile`, `--only` and `--skip`.
+#[derive(Debug, Clone, Default)]
+pub struct GroupSelection {
+    pub profile: Option<String>,
+    pub only: Vec<String>,
+    pub skip: Vec<String>,
+}
+
+/// A single pattern line together with how its matches should be materialized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Glob pattern (gitignore syntax) that selects files/directories.
+    pub pattern: String,
+    /// How matched paths are brought into the target worktree: strategies
+    /// are tried in order until one is supported by the filesystem.
+    pub strategies: Vec<Strategy>,
+    /// Whether `strategies` was given by a prefix rather than defaulted.
+    pub explicit_strategy: bool,
+    /// For matched directories, recreate the directory structure and apply
+    /// the strategy to each leaf file instead of the directory as a whole.
+    pub deep: bool,
+    /// What to do when the target path already exists.
+    pub on_conflict: OnConflict,
+    /// Section (`[group "name"]`) the entry was listed under, or [`DEFAULT_GROUP`].
+    pub group: String,
+    /// Sibling path (`<pattern> when <path>`) that must exist next to each
+    /// match, relative to the match's parent directory.
+    pub when: Option<String>,
+    /// Where matches go in the target (`<pattern> => <path>`), in place of
+    /// the pattern's literal leading directories. `None` keeps each match at
+    /// the same relative path as in the source.
+    pub destination: Option<PathBuf>,
+    /// Name of the `@source` (`<name>:` prefix) to fall back to when the
+    /// main worktree has nothing at a matched path.
+    pub source: Option<String>,
+    /// Branch glob from a `[branch "glob"]` section: the entry only applies
+    /// when the target worktree has a matching branch checked out.
+    pub branch: Option<String>,
+    /// The pattern as written, if it differed before variable expansion.
+    pub unexpanded: Option<String>,
+    /// Where the line came from, for error messages.
+    pub origin: Option<Origin>,
+}
+
+/// File and 1-based line number a config line was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: PathBuf,
+    /// `0` if the line is unknown, e.g. for values read from git config.
+    pub line: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.file.display())
+        } else {
+            write!(f, "{}:{}", self.file.display(), self.line)
+        }
+    }
+}
+
+impl Entry {
+    /// Parse a single non-comment line, splitting off optional prefixes:
+    /// a `<strategy>[,<strategy>...]:` chain (e.g. `copy:.env.local` or
+    /// `reflink,copy:node_modules`) and/or `deep:`, in any order, an
+    /// optional ` when <path>` condition suffix, and an optional
+    /// ` => <destination>` mapping before it.
+    fn parse(line: &str) -> Self {
+        Self::parse_with(line, &BTreeMap::new())
+    }
+
+    /// Like [`Entry::parse`], also accepting a `<name>:` prefix for any of
+    /// the declared `sources`.
+    fn parse_with(line: &str, sources: &BTreeMap<String, PathBuf>) -> Self {
+        let mut entry = Entry {
+            pattern: line.to_string(),
+            strategies: vec![Strategy::default()],
+            explicit_strategy: false,
+            deep: false,
+            on_conflict: OnConflict::default(),
+            group: DEFAULT_GROUP.to_string(),
+            when: None,
+            destination: None,
+            source: None,
+            branch: None,
+            unexpanded: None,
+            origin: None,
+        };
+        let mut rest = line;
+        while let Some((prefix, tail)) = rest.split_once(':') {
+            if prefix == "deep" && !entry.deep {
+                entry.deep = true;
+            } else if let Some(chain) =
+                Strategy::parse_chain(prefix).filter(|_| !entry.explicit_strategy)
+            {
+                entry.strategies = chain;
+                entry.explicit_strategy = true;
+            } else if entry.source.is_none() && sources.contains_key(prefix) {
+                entry.source = Some(prefix.to_string());
+            } else {
+                break;
+            }
+            rest = tail.trim_start();
+        }
+        if let Some((pattern, when)) = rest.rsplit_once(" when ") {
+            if !when.trim().is_empty() {
+                entry.when = Some(when.trim().to_string());
+                rest = pattern.trim_end();
+            }
+        }
+        entry.pattern = rest.to_string();
+        if let Some((pattern, destination)) = rest.split_once(" => ") {
+            entry.pattern = pattern.trim_end().to_string();
+            entry.map_to(destination.trim());
+        }
+        entry
+    }
+
+    /// Send matches to `destination` instead of their source-relative path.
+    ///
+    /// A mapped pattern is always matched from the source root, as if it
+    /// started with `/`, so that its literal prefix can be replaced.
+    fn map_to(&mut self, destination: &str) {
+        if !self.pattern.starts_with('/') && !self.pattern.starts_with('!') {
+            self.pattern.insert(0, '/');
+        }
+        self.destination = Some(PathBuf::from(destination.trim_end_matches('/')));
+    }
+
+    /// Reject destinations that could not be placed inside the target.
+    fn check_destination(&self) -> Result<()> {
+        let Some(destination) = &self.destination else {
+            return Ok(());
+        };
+        if self.pattern.starts_with('!') {
+            bail!("a negated pattern cannot have a destination");
+        }
+        let inside = destination
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+        if destination.as_os_str().is_empty() || !inside {
+            bail!(
+                "destination must be a relative path inside the target: {}",
+                destination.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Make an entry read from a config file in `dir` (relative to the
+    /// source) apply below that directory, the way a nested `.gitignore`
+    /// does: a pattern without a slash (other than a trailing one) matches
+    /// at any depth below `dir`, anything else is relative to `dir`.
+    fn rebase(&mut self, dir: &Path) {
+        let dir: Vec<_> = dir.iter().map(|c| c.to_string_lossy()).collect();
+        let dir = dir.join("/");
+        let (negation, pattern) = match self.pattern.strip_prefix('!') {
+            Some(pattern) => ("!", pattern),
+            None => ("", self.pattern.as_str()),
+        };
+        self.pattern = if pattern.trim_end_matches('/').contains('/') {
+            format!("{negation}/{dir}/{}", pattern.trim_start_matches('/'))
+        } else {
+            format!("{negation}/{dir}/**/{pattern}")
+        };
+        if let Some(destination) = &mut self.destination {
+            *destination = Path::new(&dir).join(&*destination);
+        }
+    }
+
+    /// The path (relative to the target) for a match at `rel` (relative to
+    /// the source). With a destination, the literal directories the pattern
+    /// starts with are replaced by it, so `shared/fixtures/** => test/fixtures`
+    /// puts `shared/fixtures/a.json` at `test/fixtures/a.json`.
+    pub fn destination_for(&self, rel: &Path) -> PathBuf {
+        let Some(destination) = &self.destination else {
+            return rel.to_path_buf();
+        };
+        let base: PathBuf = self
+            .pattern
+            .trim_matches('/')
+            .split('/')
+            .take_while(|part| !part.contains(['*', '?', '[', '{', '\\']))
+            .collect();
+        match rel.strip_prefix(&base) {
+            Ok(rest) if rest.as_os_str().is_empty() => destination.clone(),
+            Ok(rest) => destination.join(rest),
+            Err(_) => destination.join(rel),
+        }
+    }
+}
+
+impl From<&str> for Entry {
+    fn from(line: &str) -> Self {
+        Entry::parse(line)
+    }
+}
+
+/// Schema of `.worktreelinks.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlConfig {
+    version: u32,
+    #[serde(default)]
+    relative: bool,
+    #[serde(default)]
+    nested: bool,
+    /// Expand environment variables in patterns and paths.
+    #[serde(default)]
+    expand: bool,
+    /// Same as `expand`, but unset variables are an error rather than empty.
+    #[serde(default)]
+    strict: bool,
+    #[serde(default)]
+    profiles: BTreeMap<String, Vec<String>>,
+    /// Same as `@source <name> <path>`.
+    #[serde(default)]
+    sources: BTreeMap<String, String>,
+    #[serde(default)]
+    link: Vec<TomlLink>,
+}
+
+/// A `[[link]]` table in `.worktreelinks.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlLink {
+    pattern: toml::Spanned<String>,
+    /// Strategy or fallback chain, e.g. `"copy"` or `"reflink,copy"`.
+    mode: Option<String>,
+    #[serde(default)]
+    deep: bool,
+    #[serde(default)]
+    on_conflict: OnConflict,
+    group: Option<String>,
+    branch: Option<String>,
+    when: Option<String>,
+    /// Same as the ` => <destination>` suffix.
+    destination: Option<String>,
+    /// Same as the `<name>:` prefix.
+    source: Option<String>,
 }
 
 impl Config {
-    /// Read and parse a `.worktreelinks` file.
+    /// Read and parse a config file, followed by the fragments in
+    /// the sibling `<name>.d/` directory (`*.conf`, sorted by file name).
+    ///
+    /// The main file may be missing as long as the fragment directory exists.
     pub fn from_file(path: &Path) -> Result<Self> {
+        let mut config = Config::default();
+        config.load_layer(path)?;
+        Ok(config)
+    }
+
+    /// Append a config file and its fragments, as [`Config::from_file`].
+    /// [`STDIN`] reads a plain-format config from standard input instead.
+    fn load_layer(&mut self, path: &Path) -> Result<()> {
+        if path == Path::new(STDIN) {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read config from stdin")?;
+            // Includes are resolved against the current directory.
+            let file = Path::new("<stdin>");
+            return self.parse_into(&content, file, &Section::default(), &mut Vec::new());
+        }
+        let fragments = fragment_files(path)?;
+        if !path.exists() && !fragments.is_empty() {
+            debug!("{} not found, using fragments only", path.display());
+        } else {
+            self.load(path, &Section::default(), &mut Vec::new())?;
+        }
+        for fragment in &fragments {
+            self.load(fragment, &Section::default(), &mut Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Build a config from bare pattern lines, such as the fallback patterns
+    /// of the user config.
+    pub fn from_patterns(patterns: &[String]) -> Self {
+        Config {
+            entries: patterns.iter().map(|p| Entry::parse(p.trim())).collect(),
+            ..Config::default()
+        }
+    }
+
+    /// Append settings from git config as seen from `dir`, after the entries
+    /// already loaded: every `worktree-link.pattern` value (one line each, in
+    /// the same syntax as `.worktreelinks`), plus `worktree-link.force` and
+    /// `worktree-link.relative`. Per-worktree values apply when
+    /// `extensions.worktreeConfig` is enabled.
+    pub fn merge_git_config(&mut self, dir: &Path) -> Result<()> {
+        for value in git::config_get_all(dir, "worktree-link.pattern", false)? {
+            let line = value.value.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut entry = Entry::parse_with(line, &self.sources);
+            let origin = Origin {
+                file: PathBuf::from(value.origin),
+                line: 0,
+            };
+            entry
+                .check_destination()
+                .with_context(|| format!("{origin}: invalid mapping"))?;
+            entry.origin = Some(origin);
+            self.entries.push(entry);
+        }
+
+        let flag = |key: &str| -> Result<Option<bool>> {
+            let values = git::config_get_all(dir, key, true)?;
+            Ok(values.last().map(|v| v.value == "true"))
+        };
+        if let Some(force) = flag("worktree-link.force")? {
+            self.force = force;
+        }
+        if let Some(relative) = flag("worktree-link.relative")? {
+            self.relative = relative;
+        }
+        Ok(())
+    }
+
+    /// Append the command-line patterns (or replace every entry with them).
+    /// They don't belong to any group, so they apply whatever is selected.
+    pub fn add_extra(&mut self, extra: &ExtraPatterns) {
+        if extra.replace {
+            self.entries.clear();
+        }
+        let lines = extra
+            .patterns
+            .iter()
+            .map(|p| ("--pattern", p.trim().to_string()))
+            .chain(
+                extra
+                    .excludes
+                    .iter()
+                    .map(|p| ("--exclude", format!("!{}", p.trim()))),
+            );
+        for (flag, line) in lines {
+            let mut entry = Entry::parse_with(&line, &self.sources);
+            entry.origin = Some(Origin {
+                file: PathBuf::from(flag),
+                line: 0,
+            });
+            self.entries.push(entry);
+        }
+    }
+
+    /// Append the entries of config files found in subdirectories of
+    /// `source` (see [`crate::walker::nested_configs`]), each rebased onto
+    /// its directory. Given parents first, a deeper file can negate what a
+    /// shallower one selected. Their `@source` and `%profile` declarations
+    /// are kept; other options only apply at the top level.
+    pub fn merge_nested(&mut self, source: &Path, files: &[PathBuf]) -> Result<()> {
+        for file in files {
+            let dir = file
+                .parent()
+                .and_then(|dir| dir.strip_prefix(source).ok())
+                .with_context(|| format!("Not inside the source: {}", file.display()))?;
+            debug!("Loading nested config {}", file.display());
+            let mut nested = Config {
+                sources: self.sources.clone(),
+                ..Config::default()
+            };
+            nested.load_layer(file)?;
+            for mut entry in nested.entries {
+                entry.rebase(dir);
+                self.entries.push(entry);
+            }
+            self.sources = nested.sources;
+            self.profiles.extend(nested.profiles);
+        }
+        Ok(())
+    }
+
+    /// Use `strategies` for every entry without an explicit strategy prefix.
+    pub fn set_default_strategies(&mut self, strategies: &[Strategy]) {
+        for entry in &mut self.entries {
+            if !entry.explicit_strategy {
+                entry.strategies = strategies.to_vec();
+            }
+        }
+    }
+
+    /// Load several config files as layers, in order of increasing
+    /// precedence: later layers come after earlier ones, so their negations
+    /// cancel earlier patterns, and they can use (or redeclare) the sources
+    /// of earlier ones. Missing layers are skipped, but at least one must
+    /// exist.
+    pub fn from_layers(paths: &[PathBuf]) -> Result<Self> {
+        let mut present = paths.iter().filter(|p| exists(p)).peekable();
+        if present.peek().is_none() {
+            // Report the missing primary config with the usual error.
+            return Self::from_file(paths.first().context("No config file given")?);
+        }
+
+        let mut config = Config::default();
+        for path in present {
+            debug!("Loading config layer {}", path.display());
+            config.load_layer(path)?;
+        }
+        Ok(config)
+    }
+
+    /// Read `path` (TOML if it has a `.toml` extension) and append its
+    /// entries, starting in `section`. `stack` holds the canonical paths
+    /// of the files currently being included, to detect cycles.
+    fn load(&mut self, path: &Path, section: &Section, stack: &mut Vec<PathBuf>) -> Result<()> {
         let content = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-        Ok(Self::parse(&content))
+        let canonical = std::fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve config file: {}", path.display()))?;
+        if stack.contains(&canonical) {
+            let chain: Vec<String> = stack
+                .iter()
+                .chain([&canonical])
+                .map(|p| p.display().to_string())
+                .collect();
+            bail!("Include cycle detected: {}", chain.join(" -> "));
+        }
+
+        stack.push(canonical);
+        let result = if is_toml(path) {
+            self.parse_toml_into(&content, path)
+        } else {
+            self.parse_into(&content, path, section, stack)
+        };
+        stack.pop();
+        result
     }
 
-    /// Parse the content of a `.worktreelinks` file.
+    /// Parse the content of a `.worktreelinks` file read from `file`, appending
+    /// its entries.
     /// Lines starting with `#` are comments. Inline `#` is not stripped
     /// and is treated as part of the pattern (matching `.gitignore` semantics).
-    fn parse(content: &str) -> Self {
-        let patterns = content
-            .lines()
-            .map(|line| line.trim())
-            .filter(|line| !line.is_empty() && !line.starts_with('#'))
-            .map(|line| line.to_string())
+    /// A known strategy prefix such as `copy:` or a fallback chain such as
+    /// `reflink,copy:` selects how matches are materialized; anything else is
+    /// taken verbatim as a symlink pattern.
+    ///
+    /// Lines starting with `%` are directives that set options rather than
+    /// add patterns. `%relative` makes symlinks relative, `%nested` reads
+    /// config files in subdirectories of the source,
+    /// `%include <path>` reads another file (relative to `file`) in place,
+    /// `%profile <name> <group>...` defines a profile, `%expand` turns on
+    /// variable expansion for the following lines, and `%strict` does the
+    /// same but makes unset variables an error.
+    ///
+    /// Once expansion is on, every other line has `$VAR`, `${VAR}` and
+    /// `${VAR:-default}` replaced with environment variables before it is
+    /// interpreted.
+    ///
+    /// A `[group "name"]` line starts a group; the following entries belong
+    /// to it until the next header. A `[branch "glob"]` line keeps the group
+    /// but makes the following entries conditional on the target's branch,
+    /// until the next header. Entries start out in `section`.
+    fn parse_into(
+        &mut self,
+        content: &str,
+        file: &Path,
+        section: &Section,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let mut section = section.clone();
+        for (index, raw) in content.lines().enumerate() {
+            let raw = raw.trim();
+            let origin = Origin {
+                file: file.to_path_buf(),
+                line: index + 1,
+            };
+            if raw.is_empty() || raw.starts_with('#') {
+                continue;
+            }
+            if raw == "%expand" || raw == "%strict" {
+                section.expand = true;
+                section.strict |= raw == "%strict";
+                continue;
+            }
+            let line = if section.expand {
+                expand_vars(raw, section.strict, &|name| std::env::var(name).ok())
+                    .with_context(|| format!("{origin}: failed to expand variables"))?
+            } else {
+                raw.to_string()
+            };
+            let line = line.as_str();
+            if line == "%relative" {
+                self.relative = true;
+                continue;
+            }
+            if line == "%nested" {
+                self.nested = true;
+                continue;
+            }
+            if let Some(include) = line.strip_prefix("%include") {
+                let include = include.trim();
+                if include.is_empty() {
+                    bail!("{origin}: %include requires a path");
+                }
+                let base = file.parent().unwrap_or(Path::new("."));
+                self.load(&base.join(include), &section, stack)
+                    .with_context(|| format!("{origin}: failed to include {include}"))?;
+                continue;
+            }
+            if let Some(declaration) = line.strip_prefix("@source") {
+                let Some((name, path)) = declaration.trim().split_once(char::is_whitespace) else {
+                    bail!("{origin}: @source requires a name and a path");
+                };
+                self.declare_source(name, path.trim(), file)
+                    .with_context(|| format!("{origin}: invalid @source"))?;
+                continue;
+            }
+            if let Some(profile) = line.strip_prefix("%profile ") {
+                let mut words = profile.split_whitespace();
+                let name = words.next().unwrap_or_default();
+                let groups: Vec<String> = words.map(String::from).collect();
+                if groups.is_empty() {
+                    bail!("{origin}: %profile requires a name and at least one group");
+                }
+                self.profiles.insert(name.to_string(), groups);
+                continue;
+            }
+            match section_header(line) {
+                Some(Header::Group(name)) => {
+                    section.group = name.to_string();
+                    section.branch = None;
+                    continue;
+                }
+                Some(Header::Branch(glob)) => {
+                    branch_matcher(glob)
+                        .with_context(|| format!("{origin}: invalid branch glob"))?;
+                    section.branch = Some(glob.to_string());
+                    continue;
+                }
+                None => {}
+            }
+            let mut entry = Entry::parse_with(line, &self.sources);
+            if line != raw {
+                let written = Entry::parse_with(raw, &self.sources).pattern;
+                debug!("{origin}: `{written}` expands to `{}`", entry.pattern);
+                entry.unexpanded = Some(written);
+            }
+            entry
+                .check_destination()
+                .with_context(|| format!("{origin}: invalid mapping"))?;
+            entry.group = section.group.clone();
+            entry.branch = section.branch.clone();
+            entry.origin = Some(origin);
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+}
+
+/// Expand `$VAR`, `${VAR}` and `${VAR:-default}` in `input`, looking values
+/// up with `lookup`. The default applies when the variable is unset or empty,
+/// and `$$` is a literal `$`. Other unset variables expand to nothing, or
+/// are an error if `strict`.
+fn expand_vars(
+    input: &str,
+    strict: bool,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        let (name, default, tail) = if let Some(tail) = after.strip_prefix('$') {
+            out.push('$');
+            rest = tail;
+            continue;
+        } else if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .with_context(|| format!("unterminated variable in `{input}`"))?;
+            let (name, default) = match braced[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&braced[..end], None),
+            };
+            (name, default, &braced[end + 1..])
+        } else {
+            let len = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            (&after[..len], None, &after[len..])
+        };
+
+        if name.is_empty() {
+            // A lone `$` (e.g. at the end of a pattern) is taken literally.
+            if default.is_some() || tail.len() != after.len() {
+                bail!("invalid variable reference in `{input}`");
+            }
+            out.push('$');
+        } else if !name.chars().all(is_name_char) {
+            bail!("invalid variable name `{name}` in `{input}`");
+        } else {
+            match (lookup(name).filter(|v| !v.is_empty()), default) {
+                (Some(value), _) => out.push_str(&value),
+                (None, Some(default)) => out.push_str(default),
+                (None, None) if strict => bail!("environment variable `{name}` is not set"),
+                (None, None) => {
+                    debug!("environment variable `{name}` is not set, using an empty value");
+                }
+            }
+        }
+        rest = tail;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Parse a `[group "name"]` or `[branch "glob"]` section header, if `line`
+/// is one. Both need the keyword and quotes, so glob classes such as `[a-h]`
+/// stay patterns. Group names are limited to letters, digits, `-` and `_`.
+fn section_header(line: &str) -> Option<Header<'_>> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (keyword, quoted) = inner.split_once(' ')?;
+    let value = quoted.trim().strip_prefix('"')?.strip_suffix('"')?;
+    match keyword {
+        "branch" => Some(Header::Branch(value)),
+        "group" => {
+            let valid = !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            valid.then_some(Header::Group(value))
+        }
+        _ => None,
+    }
+}
+
+/// Compile a branch glob. As in patterns, `*` stops at `/` and `**` doesn't.
+fn branch_matcher(glob: &str) -> Result<globset::GlobMatcher> {
+    let glob = globset::GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid branch glob: {glob}"))?;
+    Ok(glob.compile_matcher())
+}
+
+impl Config {
+    /// Keep only the entries whose group is selected: the groups of the
+    /// profile and `--only` (all groups if neither is given), minus `--skip`.
+    /// Naming a group or profile that the config doesn't define is an error.
+    pub fn select_groups(&mut self, selection: &GroupSelection) -> Result<()> {
+        let mut only = selection.only.clone();
+        if let Some(name) = &selection.profile {
+            let profile = self
+                .profiles
+                .get(name)
+                .with_context(|| format!("Unknown profile: {name}"))?;
+            only.extend(profile.iter().cloned());
+        }
+
+        let known: BTreeSet<&str> = self
+            .entries
+            .iter()
+            .map(|e| e.group.as_str())
+            .chain([DEFAULT_GROUP])
             .collect();
-        Config { patterns }
+        for name in only.iter().chain(&selection.skip) {
+            if !known.contains(name.as_str()) {
+                let known: Vec<&str> = known.into_iter().collect();
+                bail!("Unknown group: {name} (known: {})", known.join(", "));
+            }
+        }
+
+        self.entries.retain(|e| {
+            (only.is_empty() || only.contains(&e.group)) && !selection.skip.contains(&e.group)
+        });
+        Ok(())
     }
+
+    /// Drop the entries of `[branch "glob"]` sections whose glob does not
+    /// match `branch`, the branch checked out in the target worktree (`None`
+    /// if detached, in which case every conditional entry is dropped).
+    pub fn select_branch(&mut self, branch: Option<&str>) -> Result<()> {
+        let mut matchers = BTreeMap::new();
+        for glob in self.entries.iter().filter_map(|e| e.branch.as_deref()) {
+            if !matchers.contains_key(glob) {
+                matchers.insert(glob.to_string(), branch_matcher(glob)?);
+            }
+        }
+        self.entries.retain(|e| match &e.branch {
+            None => true,
+            Some(glob) => branch.is_some_and(|b| matchers[glob].is_match(b)),
+        });
+        Ok(())
+    }
+
+    /// Record an `@source` declaration from `file`. A relative `path` is
+    /// resolved against the directory of `file`, and `~` against `$HOME`.
+    /// Redeclaring a name replaces its path.
+    fn declare_source(&mut self, name: &str, path: &str, file: &Path) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("invalid source name `{name}`");
+        }
+        if name == "deep" || Strategy::from_name(name).is_some() {
+            bail!("`{name}` is reserved and cannot name a source");
+        }
+        if path.is_empty() {
+            bail!("source `{name}` needs a path");
+        }
+        let home = || std::env::var_os("HOME").map(PathBuf::from);
+        let path = match path.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => home()
+                .context("cannot expand `~`: HOME is not set")?
+                .join(rest.trim_start_matches('/')),
+            _ => file.parent().unwrap_or(Path::new(".")).join(path),
+        };
+        self.sources.insert(name.to_string(), path);
+        Ok(())
+    }
+
+    /// Parse the content of a `.worktreelinks.toml` file read from `file`,
+    /// appending its entries. Each `[[link]]` maps onto the same [`Entry`]
+    /// a plain line would produce, plus the options only TOML can express.
+    fn parse_toml_into(&mut self, content: &str, file: &Path) -> Result<()> {
+        let parsed: TomlConfig = toml::from_str(content)
+            .with_context(|| format!("Invalid config file: {}", file.display()))?;
+        if parsed.version != TOML_VERSION {
+            bail!(
+                "{}: unsupported config version {} (expected {TOML_VERSION})",
+                file.display(),
+                parsed.version
+            );
+        }
+
+        // Every path-like value is expanded, as the whole line is in the
+        // line format.
+        let expand = |value: &str| {
+            if parsed.expand || parsed.strict {
+                expand_vars(value, parsed.strict, &|name| std::env::var(name).ok())
+            } else {
+                Ok(value.to_string())
+            }
+        };
+
+        self.relative |= parsed.relative;
+        self.nested |= parsed.nested;
+        self.profiles.extend(parsed.profiles);
+        for (name, path) in &parsed.sources {
+            let path = expand(path).with_context(|| {
+                format!(
+                    "{}: failed to expand variables in source {name}",
+                    file.display()
+                )
+            })?;
+            self.declare_source(name, &path, file)
+                .with_context(|| format!("{}: invalid source", file.display()))?;
+        }
+        for link in parsed.link {
+            let origin = Origin {
+                file: file.to_path_buf(),
+                line: content[..link.pattern.span().start].lines().count().max(1),
+            };
+            let written = link.pattern.get_ref().trim();
+            let expanded =
+                expand(written).with_context(|| format!("{origin}: failed to expand variables"))?;
+            let mut entry = Entry::parse_with(&expanded, &self.sources);
+            if expanded != written {
+                let written = Entry::parse_with(written, &self.sources).pattern;
+                debug!("{origin}: `{written}` expands to `{}`", entry.pattern);
+                entry.unexpanded = Some(written);
+            }
+            if let Some(mode) = &link.mode {
+                entry.strategies = Strategy::parse_chain(mode)
+                    .with_context(|| format!("{origin}: unknown mode: {mode}"))?;
+                entry.explicit_strategy = true;
+            }
+            entry.deep |= link.deep;
+            entry.on_conflict = link.on_conflict;
+            if let Some(group) = link.group {
+                entry.group = group;
+            }
+            if let Some(when) = &link.when {
+                entry.when = Some(
+                    expand(when)
+                        .with_context(|| format!("{origin}: failed to expand variables"))?,
+                );
+            }
+            if let Some(destination) = &link.destination {
+                let destination = expand(destination.trim())
+                    .with_context(|| format!("{origin}: failed to expand variables"))?;
+                entry.map_to(&destination);
+            }
+            if let Some(name) = link.source {
+                if !self.sources.contains_key(&name) {
+                    bail!("{origin}: unknown source: {name}");
+                }
+                entry.source = Some(name);
+            }
+            entry
+                .check_destination()
+                .with_context(|| format!("{origin}: invalid mapping"))?;
+            if let Some(glob) = link.branch {
+                branch_matcher(&glob).with_context(|| format!("{origin}: invalid branch glob"))?;
+                entry.branch = Some(glob);
+            }
+            entry.origin = Some(origin);
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// The project config file in `source`: `.worktreelinks.toml` if present,
+/// otherwise `.worktreelinks`.
+pub fn default_path(source: &Path) -> PathBuf {
+    let toml = source.join(".worktreelinks.toml");
+    let plain = source.join(".worktreelinks");
+    if toml.exists() {
+        if plain.exists() {
+            warn!(
+                "Both {} and {} exist; using the TOML file",
+                toml.display(),
+                plain.display()
+            );
+        }
+        toml
+    } else {
+        plain
+    }
+}
+
+/// The per-user configuration directory, `$XDG_CONFIG_HOME/worktree-link`
+/// (falling back to `~/.config/worktree-link`).
+pub fn user_config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("worktree-link"))
+}
+
+/// The config files for the project config files at `paths`, lowest
+/// precedence first: the shared project files in order, the user's personal
+/// patterns for every repository (unless `include_user` is false), then the
+/// uncommitted `<name>.local` next to each project file.
+///
+/// For a TOML file `<stem>.toml`, the local layers are `<stem>.local` (line
+/// format) and `<stem>.local.toml`, so each is parsed in its own format.
+pub fn layers(paths: &[PathBuf], include_user: bool) -> Vec<PathBuf> {
+    let mut layers = paths.to_vec();
+    if include_user {
+        layers.extend(user_config_dir().map(|dir| dir.join("worktreelinks")));
+    }
+    for path in paths.iter().filter(|p| p.as_path() != Path::new(STDIN)) {
+        if is_toml(path) {
+            layers.push(path.with_extension("local"));
+            layers.push(path.with_extension("local.toml"));
+        } else {
+            let mut local_name = path.file_name().unwrap_or_default().to_os_string();
+            local_name.push(".local");
+            layers.push(path.with_file_name(local_name));
+        }
+    }
+    layers
+}
+
+/// Whether there is any configuration at `path`: the file itself or its
+/// fragment directory. Standard input always counts.
+pub fn exists(path: &Path) -> bool {
+    path == Path::new(STDIN) || path.exists() || fragment_dir(path).is_dir()
+}
+
+fn fragment_dir(path: &Path) -> PathBuf {
+    let mut dir_name = path.file_name().unwrap_or_default().to_os_string();
+    dir_name.push(".d");
+    path.with_file_name(dir_name)
+}
+
+/// List the `*.conf` files in the fragment directory belonging to the config
+/// file at `path` (e.g. `.worktreelinks.d/` for `.worktreelinks`), sorted by
+/// name. A missing directory yields no fragments.
+fn fragment_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = fragment_dir(path);
+    let read_dir = match std::fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read fragment directory: {}", dir.display()))
+        }
+    };
+
+    let mut files = Vec::new();
+    for dir_entry in read_dir {
+        let file = dir_entry
+            .with_context(|| format!("Failed to read fragment directory: {}", dir.display()))?
+            .path();
+        if file.extension().is_some_and(|ext| ext == "conf") && file.is_file() {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Append `pattern` as a new line (or a new `[[link]]` table for TOML files)
+/// to the config file at `path`, creating the file if needed. Returns `false`
+/// (and changes nothing) if the pattern is already present.
+///
+/// In the line format, the pattern goes before the first section header (and
+/// the comments directly above it), so it stays in the `default` group and
+/// never becomes branch-conditional.
+pub fn append_pattern(path: &Path, pattern: &str) -> Result<bool> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read config file: {}", path.display()))
+        }
+    };
+    let mut updated = content;
+    if is_toml(path) {
+        if updated.is_empty() {
+            updated = format!("version = {TOML_VERSION}\n");
+        }
+        let mut config = Config::default();
+        config.parse_toml_into(&updated, path)?;
+        if config.entries.iter().any(|e| e.pattern == pattern) {
+            return Ok(false);
+        }
+        if !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        let quoted = toml::Value::String(pattern.to_string());
+        updated.push_str(&format!("\n[[link]]\npattern = {quoted}\n"));
+    } else {
+        let lines: Vec<&str> = updated.lines().collect();
+        let header = lines
+            .iter()
+            .position(|line| section_header(line.trim()).is_some());
+        let unsectioned = &lines[..header.unwrap_or(lines.len())];
+        if unsectioned.iter().any(|line| line.trim() == pattern) {
+            return Ok(false);
+        }
+        match header {
+            Some(header) => {
+                let at = lines[..header]
+                    .iter()
+                    .rposition(|line| {
+                        let line = line.trim();
+                        !line.is_empty() && !line.starts_with('#')
+                    })
+                    .map_or(0, |last| last + 1);
+                let mut lines = lines;
+                lines.insert(at, pattern);
+                // Keep the section separated from the pattern.
+                if lines[at + 1].trim().starts_with('[') {
+                    lines.insert(at + 1, "");
+                }
+                updated = lines.join("\n") + "\n";
+            }
+            None => {
+                if !updated.is_empty() && !updated.ends_with('\n') {
+                    updated.push('\n');
+                }
+                updated.push_str(pattern);
+                updated.push('\n');
+            }
+        }
+    }
+    std::fs::write(path, updated)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+    Ok(true)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse(content: &str) -> Config {
+        let mut config = Config::default();
+        config
+            .parse_into(
+                content,
+                Path::new(".worktreelinks"),
+                &Section::default(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+        config
+    }
+
+    fn patterns(config: &Config) -> Vec<&str> {
+        config.entries.iter().map(|e| e.pattern.as_str()).collect()
+    }
+
     #[test]
     fn parse_ignores_comments_and_blank_lines() {
         let input = r#"
@@ -47,22 +1097,678 @@ node_modules
 .next/
 dist/
         "#;
-        let config = Config::parse(input);
+        let config = parse(input);
         assert_eq!(
-            config.patterns,
+            patterns(&config),
             vec!["node_modules", ".env", ".env.*", ".next/", "dist/"]
         );
     }
 
     #[test]
     fn parse_empty_file() {
-        let config = Config::parse("");
-        assert!(config.patterns.is_empty());
+        let config = parse("");
+        assert!(config.entries.is_empty());
     }
 
     #[test]
     fn parse_only_comments() {
-        let config = Config::parse("# comment\n# another");
-        assert!(config.patterns.is_empty());
+        let config = parse("# comment\n# another");
+        assert!(config.entries.is_empty());
+    }
+
+    #[test]
+    fn parse_strategy_prefix() {
+        let config = parse("node_modules\ncopy:.env.local\ncopy: config/database.yml\n");
+        let parsed: Vec<(&str, &[Strategy])> = config
+            .entries
+            .iter()
+            .map(|e| (e.pattern.as_str(), e.strategies.as_slice()))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("node_modules", &[Strategy::Symlink][..]),
+                (".env.local", &[Strategy::Copy][..]),
+                ("config/database.yml", &[Strategy::Copy][..]),
+            ]
+        );
+        assert!(config.entries.iter().all(|e| !e.deep));
+        assert!(!config.entries[0].explicit_strategy);
+        assert!(config.entries[1].explicit_strategy);
+    }
+
+    #[test]
+    fn parse_unknown_prefix_is_part_of_pattern() {
+        let config = parse("foo:bar");
+        assert_eq!(patterns(&config), vec!["foo:bar"]);
+        assert_eq!(config.entries[0].strategies, vec![Strategy::Symlink]);
+
+        let config = parse("copy,bogus:x");
+        assert_eq!(patterns(&config), vec!["copy,bogus:x"]);
+    }
+
+    #[test]
+    fn parse_relative_directive() {
+        let config = parse("%relative\n.env\n");
+        assert!(config.relative);
+        assert_eq!(patterns(&config), vec![".env"]);
+        assert!(!parse(".env").relative);
+    }
+
+    #[test]
+    fn parse_nested_directive() {
+        let config = parse(
+            "%nested
+.env
+",
+        );
+        assert!(config.nested);
+        assert_eq!(patterns(&config), vec![".env"]);
+        assert!(!parse(".env").nested);
+
+        let mut config = Config::default();
+        config
+            .parse_toml_into(
+                "version = 1\nnested = true\n",
+                Path::new(".worktreelinks.toml"),
+            )
+            .unwrap();
+        assert!(config.nested);
+    }
+
+    #[test]
+    fn parse_deep_prefix_combines_with_strategy() {
+        let config = parse("deep:.vscode/\ndeep:copy:fixtures\ncopy:deep:tmp\ndeep:deep:x");
+        assert_eq!(
+            patterns(&config),
+            vec![".vscode/", "fixtures", "tmp", "deep:x"]
+        );
+        assert!(config.entries.iter().all(|e| e.deep));
+        assert_eq!(config.entries[0].strategies, vec![Strategy::Symlink]);
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Copy]);
+        assert_eq!(config.entries[2].strategies, vec![Strategy::Copy]);
+    }
+
+    #[test]
+    fn append_pattern_adds_line_once() {
+        let dir = std::env::temp_dir().join("worktree-link-test-append_pattern");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".worktreelinks");
+        std::fs::write(&path, "node_modules").unwrap();
+
+        assert!(append_pattern(&path, "/.env.local").unwrap());
+        assert!(!append_pattern(&path, "/.env.local").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "node_modules\n/.env.local\n"
+        );
+
+        let fresh = dir.join("new");
+        assert!(append_pattern(&fresh, "tmp/").unwrap());
+        assert_eq!(std::fs::read_to_string(&fresh).unwrap(), "tmp/\n");
+
+        // Never into a trailing section, where it would be conditional.
+        let sectioned = dir.join("sectioned");
+        std::fs::write(
+            &sectioned,
+            ".env\n\n# Release only\n[branch \"release/*\"]\n/node_modules\n",
+        )
+        .unwrap();
+        assert!(append_pattern(&sectioned, "/node_modules").unwrap());
+        assert!(!append_pattern(&sectioned, "/node_modules").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&sectioned).unwrap(),
+            ".env\n/node_modules\n\n# Release only\n[branch \"release/*\"]\n/node_modules\n"
+        );
+        let config = Config::from_file(&sectioned).unwrap();
+        assert_eq!(config.entries[1].pattern, "/node_modules");
+        assert_eq!(config.entries[1].branch, None);
+
+        std::fs::write(&sectioned, "[group \"cache\"]\n.cache/\n").unwrap();
+        assert!(append_pattern(&sectioned, "/.env.local").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&sectioned).unwrap(),
+            "/.env.local\n\n[group \"cache\"]\n.cache/\n"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parse_fallback_chain() {
+        let config = parse("reflink, copy ,symlink:node_modules");
+        assert_eq!(patterns(&config), vec!["node_modules"]);
+        assert_eq!(
+            config.entries[0].strategies,
+            vec![Strategy::Reflink, Strategy::Copy, Strategy::Symlink]
+        );
+    }
+
+    #[test]
+    fn from_file_follows_includes_and_fragments() {
+        let dir = std::env::temp_dir().join("worktree-link-test-includes");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join(".worktreelinks.d")).unwrap();
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        let path = dir.join(".worktreelinks");
+        std::fs::write(&path, "node_modules\n%include shared/secrets\n.next/\n").unwrap();
+        std::fs::write(dir.join("shared/secrets"), "# secrets\n%relative\n.env\n").unwrap();
+        std::fs::write(dir.join(".worktreelinks.d/20-backend.conf"), ".venv\n").unwrap();
+        std::fs::write(dir.join(".worktreelinks.d/10-frontend.conf"), "dist/\n").unwrap();
+        std::fs::write(dir.join(".worktreelinks.d/notes.txt"), "ignored\n").unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(
+            patterns(&config),
+            vec!["node_modules", ".env", ".next/", "dist/", ".venv"]
+        );
+        assert!(config.relative);
+        assert_eq!(
+            config.entries[1].origin,
+            Some(Origin {
+                file: dir.join("shared/secrets"),
+                line: 3,
+            })
+        );
+        assert_eq!(
+            config.entries[4].origin.as_ref().unwrap().to_string(),
+            format!(
+                "{}:1",
+                dir.join(".worktreelinks.d/20-backend.conf").display()
+            )
+        );
+
+        // Fragments alone are enough.
+        std::fs::remove_file(&path).unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(patterns(&config), vec!["dist/", ".venv"]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn from_file_rejects_include_cycles() {
+        let dir = std::env::temp_dir().join("worktree-link-test-include-cycle");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".worktreelinks");
+        std::fs::write(&path, "a\n%include other\n").unwrap();
+        std::fs::write(dir.join("other"), "b\n%include .worktreelinks\n").unwrap();
+
+        let err = format!("{:#}", Config::from_file(&path).unwrap_err());
+        assert!(err.contains("Include cycle detected"), "{err}");
+        assert!(err.contains(":2: failed to include other"), "{err}");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn from_layers_appends_later_layers() {
+        let dir = std::env::temp_dir().join("worktree-link-test-layers");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".worktreelinks");
+        std::fs::write(&path, ".env*\n").unwrap();
+        std::fs::write(
+            dir.join(".worktreelinks.local"),
+            "%relative\n!.env.prod\n.idea/\n",
+        )
+        .unwrap();
+
+        let layers = vec![
+            path.clone(),
+            dir.join("missing"),
+            dir.join(".worktreelinks.local"),
+        ];
+        let config = Config::from_layers(&layers).unwrap();
+        assert_eq!(patterns(&config), vec![".env*", "!.env.prod", ".idea/"]);
+        assert!(config.relative);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(dir.join(".worktreelinks.local")).unwrap();
+        let err = Config::from_layers(&layers).unwrap_err();
+        assert!(err.to_string().contains("Failed to read config file"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn layers_put_local_file_last() {
+        let layers = layers(&[PathBuf::from("/repo/.worktreelinks")], true);
+        assert_eq!(layers.first().unwrap(), Path::new("/repo/.worktreelinks"));
+        assert_eq!(
+            layers.last().unwrap(),
+            Path::new("/repo/.worktreelinks.local")
+        );
+
+        let paths = [
+            PathBuf::from("/repo/a"),
+            PathBuf::from(STDIN),
+            PathBuf::from("/repo/b"),
+        ];
+        assert_eq!(
+            super::layers(&paths, false),
+            vec![
+                PathBuf::from("/repo/a"),
+                PathBuf::from(STDIN),
+                PathBuf::from("/repo/b"),
+                PathBuf::from("/repo/a.local"),
+                PathBuf::from("/repo/b.local"),
+            ]
+        );
+
+        assert_eq!(
+            super::layers(&[PathBuf::from("/repo/.worktreelinks.toml")], false),
+            vec![
+                PathBuf::from("/repo/.worktreelinks.toml"),
+                PathBuf::from("/repo/.worktreelinks.local"),
+                PathBuf::from("/repo/.worktreelinks.local.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn add_extra_appends_or_replaces() {
+        let mut config = parse("@source certs /certs\n.env\n");
+        let mut extra = ExtraPatterns {
+            patterns: vec!["copy:fixtures/**".into(), "certs:*.pem".into()],
+            excludes: vec!["fixtures/large".into()],
+            replace: false,
+        };
+        config.add_extra(&extra);
+        assert_eq!(
+            patterns(&config),
+            vec![".env", "fixtures/**", "*.pem", "!fixtures/large"]
+        );
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Copy]);
+        assert_eq!(config.entries[2].source.as_deref(), Some("certs"));
+        assert_eq!(
+            config.entries[3].origin.as_ref().unwrap().to_string(),
+            "--exclude"
+        );
+
+        extra.replace = true;
+        config.add_extra(&extra);
+        assert_eq!(config.entries.len(), 3);
+    }
+
+    #[test]
+    fn set_default_strategies_keeps_explicit_prefixes() {
+        let mut config = parse("node_modules\nsymlink:.env\ncopy:tmp/");
+        config.set_default_strategies(&[Strategy::Reflink, Strategy::Copy]);
+        assert_eq!(
+            config.entries[0].strategies,
+            vec![Strategy::Reflink, Strategy::Copy]
+        );
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Symlink]);
+        assert_eq!(config.entries[2].strategies, vec![Strategy::Copy]);
+    }
+
+    #[test]
+    fn from_file_reads_toml_links() {
+        let dir = std::env::temp_dir().join("worktree-link-test-toml");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".worktreelinks.toml");
+        std::fs::write(
+            &path,
+            r#"version = 1
+relative = true
+
+[[link]]
+pattern = "node_modules"
+mode = "reflink,copy"
+deep = true
+
+[[link]]
+pattern = ".env.local"
+mode = "copy"
+on_conflict = "backup"
+
+[[link]]
+pattern = "!.env.production"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        assert!(config.relative);
+        assert_eq!(
+            patterns(&config),
+            vec!["node_modules", ".env.local", "!.env.production"]
+        );
+        let first = &config.entries[0];
+        assert_eq!(first.strategies, vec![Strategy::Reflink, Strategy::Copy]);
+        assert!(first.deep && first.explicit_strategy);
+        assert_eq!(config.entries[1].on_conflict, OnConflict::Backup);
+        assert_eq!(config.entries[1].origin.as_ref().unwrap().line, 10);
+        assert_eq!(config.entries[2].strategies, vec![Strategy::Symlink]);
+        assert_eq!(config.entries[2].on_conflict, OnConflict::Skip);
+
+        assert!(append_pattern(&path, "/.idea").unwrap());
+        assert!(!append_pattern(&path, "/.idea").unwrap());
+        assert_eq!(patterns(&Config::from_file(&path).unwrap()).len(), 4);
+
+        std::fs::write(&path, "version = 2\n").unwrap();
+        let err = format!("{:#}", Config::from_file(&path).unwrap_err());
+        assert!(err.contains("unsupported config version 2"), "{err}");
+
+        std::fs::write(
+            &path,
+            "version = 1\n[[link]]\npattern = \"x\"\nmode = \"teleport\"\n",
+        )
+        .unwrap();
+        let err = format!("{:#}", Config::from_file(&path).unwrap_err());
+        assert!(err.contains(":3: unknown mode: teleport"), "{err}");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn select_groups_filters_by_section() {
+        let input = "\
+%profile ci cache
+node_modules
+[group \"cache\"]
+.cache/
+[group \"secrets\"]
+.env
+!.env.example
+[ab]c
+[a-h]
+[group \"default\"]
+dist/
+";
+        let selected = |selection: GroupSelection| {
+            let mut config = parse(input);
+            config.select_groups(&selection).map(|()| {
+                patterns(&config)
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let all = selected(GroupSelection::default()).unwrap();
+        assert_eq!(
+            all,
+            vec![
+                "node_modules",
+                ".cache/",
+                ".env",
+                "!.env.example",
+                "[ab]c",
+                "[a-h]",
+                "dist/"
+            ]
+        );
+        let only = selected(GroupSelection {
+            only: vec!["cache".into()],
+            ..Default::default()
+        });
+        assert_eq!(only.unwrap(), vec![".cache/"]);
+        let skip = selected(GroupSelection {
+            skip: vec!["secrets".into()],
+            ..Default::default()
+        });
+        assert_eq!(skip.unwrap(), vec!["node_modules", ".cache/", "dist/"]);
+        let profile = selected(GroupSelection {
+            profile: Some("ci".into()),
+            only: vec!["default".into()],
+            ..Default::default()
+        });
+        assert_eq!(profile.unwrap(), vec!["node_modules", ".cache/", "dist/"]);
+
+        let err = selected(GroupSelection {
+            only: vec!["cahce".into()],
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown group: cahce (known: cache, default, secrets)"
+        );
+        assert!(selected(GroupSelection {
+            profile: Some("nope".into()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn select_branch_keeps_matching_conditional_entries() {
+        let input = "\
+.env*
+[branch \"release/*\"]
+!.env.development
+[branch \"feature/**\"]
+.env.development
+[group \"secrets\"]
+certs/
+";
+        let selected = |branch: Option<&str>| {
+            let mut config = parse(input);
+            config.select_branch(branch).unwrap();
+            patterns(&config)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            selected(Some("release/1.2")),
+            vec![".env*", "!.env.development", "certs/"]
+        );
+        assert_eq!(
+            selected(Some("feature/team/login")),
+            vec![".env*", ".env.development", "certs/"]
+        );
+        // `*` does not cross `/`.
+        assert_eq!(
+            selected(Some("release/1.2/hotfix")),
+            vec![".env*", "certs/"]
+        );
+        assert_eq!(selected(None), vec![".env*", "certs/"]);
+        assert_eq!(parse(input).entries[3].group, "secrets");
+    }
+
+    #[test]
+    fn parse_when_condition() {
+        let config =
+            parse("node_modules when package.json\ncopy:.venv  when  pyproject.toml\nwhen\n");
+        assert_eq!(patterns(&config), vec!["node_modules", ".venv", "when"]);
+        assert_eq!(config.entries[0].when.as_deref(), Some("package.json"));
+        assert_eq!(config.entries[1].when.as_deref(), Some("pyproject.toml"));
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Copy]);
+        assert_eq!(config.entries[2].when, None);
+    }
+
+    #[test]
+    fn parse_destination_mapping() {
+        let config = parse(
+            "shared/fixtures/ => test/fixtures/\n\
+             copy:config/master.key => config/credentials/development.key\n\
+             shared/*.json => fixtures when package.json\n",
+        );
+        assert_eq!(
+            patterns(&config),
+            vec!["/shared/fixtures/", "/config/master.key", "/shared/*.json"]
+        );
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Copy]);
+        assert_eq!(config.entries[2].when.as_deref(), Some("package.json"));
+
+        let dest = |i: usize, rel: &str| config.entries[i].destination_for(Path::new(rel));
+        assert_eq!(dest(0, "shared/fixtures"), Path::new("test/fixtures"));
+        assert_eq!(
+            dest(1, "config/master.key"),
+            Path::new("config/credentials/development.key")
+        );
+        assert_eq!(dest(2, "shared/a.json"), Path::new("fixtures/a.json"));
+
+        for bad in ["!a => b\n", "a => ../b\n", "a => /abs\n"] {
+            let mut config = Config::default();
+            let err = config
+                .parse_into(bad, Path::new("x"), &Section::default(), &mut Vec::new())
+                .unwrap_err();
+            assert!(
+                format!("{err:#}").starts_with("x:1: invalid mapping"),
+                "{err:#}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_source_declarations_and_prefixes() {
+        let config = parse(
+            "@source certs ~/shared/certs\n\
+             @source web ../web\n\
+             certs:dev.pem\n\
+             copy:web:build/\n\
+             other:file\n",
+        );
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        assert_eq!(config.sources["certs"], home.join("shared/certs"));
+        assert_eq!(config.sources["web"], Path::new("../web"));
+        assert_eq!(patterns(&config), vec!["dev.pem", "build/", "other:file"]);
+        assert_eq!(config.entries[0].source.as_deref(), Some("certs"));
+        assert_eq!(config.entries[1].source.as_deref(), Some("web"));
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Copy]);
+        assert_eq!(config.entries[2].source, None);
+
+        for bad in ["@source copy x\n", "@source lonely\n", "@source a/b x\n"] {
+            let mut config = Config::default();
+            let err = config
+                .parse_into(bad, Path::new("x"), &Section::default(), &mut Vec::new())
+                .unwrap_err();
+            assert!(format!("{err:#}").starts_with("x:1: "), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn merge_nested_rebases_patterns_onto_their_directory() {
+        let dir = std::env::temp_dir().join("worktree-link-test-nested");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("packages/web/app")).unwrap();
+        let web = dir.join("packages/web/.worktreelinks");
+        let app = dir.join("packages/web/app/.worktreelinks");
+        std::fs::write(&web, ".next/\n/.env\nconfig/*.key => keys\n").unwrap();
+        std::fs::write(&app, "!.next/\n").unwrap();
+
+        let mut config = parse("node_modules\n");
+        config.merge_nested(&dir, &[web, app]).unwrap();
+        assert_eq!(
+            patterns(&config),
+            vec![
+                "node_modules",
+                "/packages/web/**/.next/",
+                "/packages/web/.env",
+                "/packages/web/config/*.key",
+                "!/packages/web/app/**/.next/",
+            ]
+        );
+        assert_eq!(
+            config.entries[3].destination_for(Path::new("packages/web/config/a.key")),
+            Path::new("packages/web/keys/a.key")
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expand_vars_handles_defaults_and_strict_mode() {
+        let lookup = |name: &str| match name {
+            "TARGET_DIR" => Some("build".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let expand = |input: &str, strict: bool| expand_vars(input, strict, &lookup);
+
+        assert_eq!(expand("$TARGET_DIR/", false).unwrap(), "build/");
+        assert_eq!(expand("${TARGET_DIR}-x", false).unwrap(), "build-x");
+        assert_eq!(
+            expand("${CARGO_TARGET_DIR:-target}", true).unwrap(),
+            "target"
+        );
+        assert_eq!(expand("${EMPTY:-fallback}", true).unwrap(), "fallback");
+        assert_eq!(expand("a$$b $", false).unwrap(), "a$b $");
+        assert_eq!(expand("x/$UNSET/y", false).unwrap(), "x//y");
+
+        let err = expand("x/$UNSET/y", true).unwrap_err();
+        assert_eq!(err.to_string(), "environment variable `UNSET` is not set");
+        assert!(expand("${UNTERMINATED", false).is_err());
+        assert!(expand("${BAD-NAME}", false).is_err());
+    }
+
+    #[test]
+    fn parse_expands_variables_in_lines() {
+        let config = parse("$WTL_TEST_SURELY_UNSET.env\n");
+        assert_eq!(patterns(&config), vec!["$WTL_TEST_SURELY_UNSET.env"]);
+        assert_eq!(config.entries[0].unexpanded, None);
+
+        let config =
+            parse("%expand\n${WTL_TEST_SURELY_UNSET:-target}/\ncopy:$WTL_TEST_SURELY_UNSET.env\n");
+        assert_eq!(patterns(&config), vec!["target/", ".env"]);
+        assert_eq!(
+            config.entries[0].unexpanded.as_deref(),
+            Some("${WTL_TEST_SURELY_UNSET:-target}/")
+        );
+        assert_eq!(config.entries[1].strategies, vec![Strategy::Copy]);
+
+        let mut strict = Config::default();
+        let err = strict
+            .parse_into(
+                "ok\n%strict\n$WTL_TEST_SURELY_UNSET\n",
+                Path::new(".worktreelinks"),
+                &Section::default(),
+                &mut Vec::new(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            ".worktreelinks:3: failed to expand variables: environment variable `WTL_TEST_SURELY_UNSET` is not set"
+        );
+    }
+
+    #[test]
+    fn parse_toml_expands_variables_in_every_path() {
+        let mut config = Config::default();
+        config
+            .parse_toml_into(
+                r#"
+version = 1
+expand = true
+[sources]
+shared = "${WTL_TEST_SURELY_UNSET:-../shared}"
+
+[[link]]
+pattern = "${WTL_TEST_SURELY_UNSET:-config}/app.toml"
+destination = "${WTL_TEST_SURELY_UNSET:-settings}/app.toml"
+when = "${WTL_TEST_SURELY_UNSET:-package.json}"
+source = "shared"
+"#,
+                Path::new("/repo/.worktreelinks.toml"),
+            )
+            .unwrap();
+        assert_eq!(config.sources["shared"], Path::new("/repo/../shared"));
+        let entry = &config.entries[0];
+        assert_eq!(entry.pattern, "/config/app.toml");
+        assert_eq!(
+            entry.destination.as_deref(),
+            Some(Path::new("settings/app.toml"))
+        );
+        assert_eq!(entry.when.as_deref(), Some("package.json"));
+
+        let mut strict = Config::default();
+        let err = strict
+            .parse_toml_into(
+                "version = 1\nstrict = true\n[[link]]\npattern = \"x\"\ndestination = \"$WTL_TEST_SURELY_UNSET\"\n",
+                Path::new(".worktreelinks.toml"),
+            )
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains(".worktreelinks.toml:4: failed to expand variables"),
+            "{err:#}"
+        );
     }
 }
@@ -3,11 +3,145 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// A worktree as reported by `git worktree list --porcelain`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Worktree {
+    pub path: PathBuf,
+    /// Short branch name (without `refs/heads/`), or `None` if detached/bare.
+    pub branch: Option<String>,
+}
+
 /// Detect the main worktree from a specific directory by running `git worktree list --porcelain`.
 ///
 /// The first entry in porcelain output is always the main worktree.
 /// Returns the canonicalized path of the main worktree.
 pub(crate) fn detect_main_worktree_in(dir: &Path) -> Result<PathBuf> {
+    let stdout = worktree_list_porcelain(dir)?;
+    parse_main_worktree(&stdout)
+}
+
+/// List all worktrees of the repository containing `dir`, main worktree first.
+pub(crate) fn list_worktrees_in(dir: &Path) -> Result<Vec<Worktree>> {
+    let stdout = worktree_list_porcelain(dir)?;
+    Ok(parse_worktrees(&stdout))
+}
+
+/// The branch checked out in the worktree at `dir` (which must be the
+/// worktree's canonical root), or `None` if it is detached or not a worktree.
+pub(crate) fn branch_in(dir: &Path) -> Result<Option<String>> {
+    let worktrees = list_worktrees_in(dir)?;
+    Ok(worktrees
+        .into_iter()
+        .find(|wt| wt.path == dir)
+        .and_then(|wt| wt.branch))
+}
+
+/// Resolve the git common directory (shared by all worktrees) for `dir`.
+pub(crate) fn common_dir_in(dir: &Path) -> Result<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--git-common-dir"])
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "`git rev-parse --git-common-dir` exited with {}.\nstderr:\n{}",
+            output.status,
+            stderr.trim_end(),
+        );
+    }
+
+    // The path may be relative to `dir` (e.g. `.git`).
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let common = dir.join(stdout.trim_end());
+    fs::canonicalize(&common)
+        .with_context(|| format!("Git common directory not found: {}", common.display()))
+}
+
+/// List the files tracked by git in `dir`, relative to `dir`.
+pub(crate) fn ls_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args(["ls-files", "-z"])
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "`git ls-files` exited with {}.\nstderr:\n{}",
+            output.status,
+            stderr.trim_end(),
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// A value read from git config, with where it was defined.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ConfigValue {
+    /// File the value came from (as printed by `--show-origin`, without the
+    /// `file:` prefix), e.g. `.git/config` or `~/.gitconfig`.
+    pub origin: String,
+    pub value: String,
+}
+
+/// Read every value of the multi-valued `key` as seen from `dir`, in git's
+/// precedence order (last wins). Running inside `dir` means per-worktree
+/// values from `config.worktree` are included when `extensions.worktreeConfig`
+/// is enabled. `as_bool` normalizes values to `true`/`false`.
+///
+/// Returns an empty list if the key is not set.
+pub(crate) fn config_get_all(dir: &Path, key: &str, as_bool: bool) -> Result<Vec<ConfigValue>> {
+    let mut command = Command::new("git");
+    command.args(["config", "--show-origin"]);
+    if as_bool {
+        command.arg("--type=bool");
+    }
+    let output = command
+        .args(["--get-all", key])
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+
+    // Exit code 1 means the key is not set.
+    if output.status.code() == Some(1) {
+        return Ok(Vec::new());
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "`git config --get-all {key}` exited with {}.\nstderr:\n{}",
+            output.status,
+            stderr.trim_end(),
+        );
+    }
+
+    Ok(parse_config_values(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Parse `git config --show-origin` output (`<origin>\t<value>` per line).
+fn parse_config_values(output: &str) -> Vec<ConfigValue> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(origin, value)| ConfigValue {
+            origin: origin.strip_prefix("file:").unwrap_or(origin).to_string(),
+            value: value.to_string(),
+        })
+        .collect()
+}
+
+fn worktree_list_porcelain(dir: &Path) -> Result<String> {
     let output = Command::new("git")
         .args(["worktree", "list", "--porcelain"])
         .current_dir(dir)
@@ -23,8 +157,7 @@ pub(crate) fn detect_main_worktree_in(dir: &Path) -> Result<PathBuf> {
         );
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    parse_main_worktree(&stdout)
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
 /// Parse the first worktree path from `git worktree list --porcelain` output.
@@ -43,6 +176,30 @@ fn parse_main_worktree(porcelain_output: &str) -> Result<PathBuf> {
     bail!("Failed to detect main worktree from git output. Use --source to specify it manually.")
 }
 
+/// Parse all entries from `git worktree list --porcelain` output.
+///
+/// Paths are canonicalized when they exist so they compare equal to the
+/// canonical source/target directories; missing (prunable) worktrees keep
+/// the path git reported.
+fn parse_worktrees(porcelain_output: &str) -> Vec<Worktree> {
+    let mut worktrees: Vec<Worktree> = Vec::new();
+    for line in porcelain_output.lines() {
+        if let Some(path_str) = line.strip_prefix("worktree ") {
+            let path = PathBuf::from(path_str);
+            worktrees.push(Worktree {
+                path: fs::canonicalize(&path).unwrap_or(path),
+                branch: None,
+            });
+        } else if let Some(branch) = line.strip_prefix("branch ") {
+            if let Some(current) = worktrees.last_mut() {
+                let short = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+                current.branch = Some(short.to_string());
+            }
+        }
+    }
+    worktrees
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +267,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn common_dir_is_shared_by_linked_worktrees() {
+        let main_dir = git_tempdir("common_dir");
+        let status = Command::new("git")
+            .args(["commit", "--allow-empty", "-m", "init", "--quiet"])
+            .current_dir(&main_dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let wt_dir = std::env::temp_dir().join("worktree-link-test-common_dir_wt");
+        let _ = fs::remove_dir_all(&wt_dir);
+        let status = Command::new("git")
+            .args(["worktree", "add", "--quiet", wt_dir.to_str().unwrap()])
+            .current_dir(&main_dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let expected = main_dir.join(".git");
+        assert_eq!(common_dir_in(&main_dir).unwrap(), expected);
+        assert_eq!(common_dir_in(&wt_dir).unwrap(), expected);
+
+        let _ = Command::new("git")
+            .args(["worktree", "remove", "--force", wt_dir.to_str().unwrap()])
+            .current_dir(&main_dir)
+            .status();
+        let _ = fs::remove_dir_all(&wt_dir);
+    }
+
     #[test]
     fn parse_main_worktree_extracts_first_entry() {
         let dir = git_tempdir("parse_first");
@@ -135,6 +322,41 @@ mod tests {
         let _ = fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn parse_worktrees_reads_paths_and_branches() {
+        let output = "\
+worktree /nonexistent/main
+HEAD 0123456789abcdef0123456789abcdef01234567
+branch refs/heads/main
+
+worktree /nonexistent/feature
+HEAD 0123456789abcdef0123456789abcdef01234567
+branch refs/heads/feature/login
+
+worktree /nonexistent/bisect
+HEAD 0123456789abcdef0123456789abcdef01234567
+detached
+";
+        let worktrees = parse_worktrees(output);
+        assert_eq!(
+            worktrees,
+            vec![
+                Worktree {
+                    path: PathBuf::from("/nonexistent/main"),
+                    branch: Some("main".into()),
+                },
+                Worktree {
+                    path: PathBuf::from("/nonexistent/feature"),
+                    branch: Some("feature/login".into()),
+                },
+                Worktree {
+                    path: PathBuf::from("/nonexistent/bisect"),
+                    branch: None,
+                },
+            ]
+        );
+    }
+
     fn git_tempdir(name: &str) -> PathBuf {
         let dir = std::env::temp_dir().join(format!("worktree-link-test-{name}"));
         let _ = fs::remove_dir_all(&dir);
@@ -168,4 +390,84 @@ mod tests {
         fs::create_dir_all(&dir).unwrap();
         fs::canonicalize(&dir).unwrap()
     }
+
+    #[test]
+    fn parse_config_values_splits_origin() {
+        let values = parse_config_values(
+            "file:.git/config\t.env\nfile:.git/config.worktree\tnode_modules\n",
+        );
+        assert_eq!(
+            values,
+            vec![
+                ConfigValue {
+                    origin: ".git/config".into(),
+                    value: ".env".into(),
+                },
+                ConfigValue {
+                    origin: ".git/config.worktree".into(),
+                    value: "node_modules".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn config_get_all_reads_per_worktree_values() {
+        let main_dir = git_tempdir("config_get_all");
+        let wt_dir = main_dir.with_file_name(format!(
+            "{}-wt",
+            main_dir.file_name().unwrap().to_string_lossy()
+        ));
+        let _ = fs::remove_dir_all(&wt_dir);
+        let git = |dir: &Path, args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(dir)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?} failed");
+        };
+        git(&main_dir, &["commit", "--allow-empty", "-m", "init"]);
+        git(
+            &main_dir,
+            &["worktree", "add", "-b", "feature", wt_dir.to_str().unwrap()],
+        );
+        git(&main_dir, &["config", "extensions.worktreeConfig", "true"]);
+        git(
+            &main_dir,
+            &["config", "--add", "worktree-link.pattern", ".env"],
+        );
+        git(
+            &wt_dir,
+            &[
+                "config",
+                "--worktree",
+                "worktree-link.pattern",
+                "node_modules",
+            ],
+        );
+        git(
+            &wt_dir,
+            &["config", "--worktree", "worktree-link.force", "yes"],
+        );
+
+        let values = |dir: &Path| -> Vec<String> {
+            config_get_all(dir, "worktree-link.pattern", false)
+                .unwrap()
+                .into_iter()
+                .map(|v| v.value)
+                .collect()
+        };
+        assert_eq!(values(&main_dir), vec![".env"]);
+        assert_eq!(values(&wt_dir), vec![".env", "node_modules"]);
+        let force = config_get_all(&wt_dir, "worktree-link.force", true).unwrap();
+        assert_eq!(force.last().unwrap().value, "true");
+        assert!(config_get_all(&main_dir, "worktree-link.force", true)
+            .unwrap()
+            .is_empty());
+
+        let _ = fs::remove_dir_all(&main_dir);
+        let _ = fs::remove_dir_all(&wt_dir);
+    }
 }
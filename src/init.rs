@@ -0,0 +1,278 @@
+use anyhow::{Context, Result};
+use ignore::WalkBuilder;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Entries at least this large are listed as large.
+const LARGE: u64 = 100 * 1024 * 1024;
+/// Entries at least this large (and not large) are listed as medium.
+const MEDIUM: u64 = 1024 * 1024;
+
+/// Well-known ignored directories that are worth sharing between worktrees,
+/// in the order they are written.
+const ECOSYSTEMS: &[(&str, &str)] = &[
+    ("node_modules", "Node.js dependencies"),
+    (".venv", "Python virtual environments"),
+    ("target", "Rust build output"),
+    (".next", "Next.js build cache"),
+];
+
+/// Heading for `.env*` files, which are grouped by prefix rather than name.
+const ENV_FILES: &str = "Environment files";
+
+/// A gitignored entry that exists in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found {
+    /// Path relative to the source.
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Total size of the files it contains, in bytes.
+    pub size: u64,
+}
+
+impl Found {
+    fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// An anchored pattern matching exactly this entry.
+    fn pattern(&self) -> String {
+        let slash = if self.is_dir { "/" } else { "" };
+        format!("/{}{slash}", self.path.display())
+    }
+
+    /// The heading of the known ecosystem this entry belongs to, if any.
+    fn ecosystem(&self) -> Option<&'static str> {
+        let name = self.name();
+        if !self.is_dir && name.starts_with(".env") {
+            return Some(ENV_FILES);
+        }
+        ECOSYSTEMS
+            .iter()
+            .find(|(dir, _)| self.is_dir && name == *dir)
+            .map(|(_, heading)| *heading)
+    }
+}
+
+/// Find the entries in `source` that are both gitignored and present.
+///
+/// Only the outermost ignored entry is reported (`node_modules`, not the
+/// files inside it). This walks with the same `ignore` settings as
+/// [`crate::walker::collect_targets`]: every entry the walk does not visit
+/// but that exists on disk is ignored.
+pub fn scan(source: &Path) -> Result<Vec<Found>> {
+    let walker = WalkBuilder::new(source)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+
+    let mut visible = HashSet::new();
+    let mut dirs = Vec::new();
+    for entry in walker {
+        let entry = entry.with_context(|| "Error walking directory")?;
+        if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+            dirs.push(entry.path().to_path_buf());
+        }
+        visible.insert(entry.into_path());
+    }
+
+    let mut found = Vec::new();
+    for dir in &dirs {
+        let children =
+            fs::read_dir(dir).with_context(|| format!("Failed to read dir: {}", dir.display()))?;
+        for child in children {
+            let child = child.with_context(|| "Error walking directory")?;
+            let path = child.path();
+            let name = child.file_name();
+            // Our own personal layers are usually ignored, but never shared.
+            if visible.contains(&path)
+                || name == ".git"
+                || name.to_string_lossy().starts_with(".worktreelinks")
+            {
+                continue;
+            }
+            let is_dir = child.file_type().is_ok_and(|ft| ft.is_dir());
+            found.push(Found {
+                path: path
+                    .strip_prefix(source)
+                    .with_context(|| "Path is not relative to source")?
+                    .to_path_buf(),
+                is_dir,
+                size: disk_size(&path),
+            });
+        }
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+/// Total size of the files at or below `path`, without following symlinks.
+/// Unreadable entries count as empty.
+fn disk_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|children| {
+            children
+                .filter_map(|child| child.ok())
+                .map(|child| disk_size(&child.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Render a commented `.worktreelinks` for `found`.
+///
+/// Entries of a known ecosystem are enabled, one anchored pattern per entry
+/// found, so that a same-named directory elsewhere in the tree isn't picked
+/// up. Everything else is listed commented out by size, largest first, so
+/// sharing it is a deliberate choice.
+pub fn render(found: &[Found]) -> String {
+    let mut out = String::from(
+        "# Generated by `wtl init` from the gitignored entries present in the main worktree.\n\
+         # Known dependency and build directories are enabled; uncomment anything else\n\
+         # that worktrees should share.\n",
+    );
+
+    let headings = ECOSYSTEMS
+        .iter()
+        .map(|(_, heading)| *heading)
+        .chain([ENV_FILES]);
+    for heading in headings {
+        let matches: Vec<&Found> = found
+            .iter()
+            .filter(|f| f.ecosystem() == Some(heading))
+            .collect();
+        if matches.is_empty() {
+            continue;
+        }
+        let total = matches.iter().map(|f| f.size).sum();
+        let _ = writeln!(
+            out,
+            "\n# {heading} ({} found, {})",
+            matches.len(),
+            format_size(total)
+        );
+        for f in matches {
+            let _ = writeln!(out, "{}", f.pattern());
+        }
+    }
+
+    let mut others: Vec<&Found> = found.iter().filter(|f| f.ecosystem().is_none()).collect();
+    others.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    let buckets = [
+        ("Large (100 MB or more)", LARGE, u64::MAX),
+        ("Medium (1 MB to 100 MB)", MEDIUM, LARGE),
+        ("Small (under 1 MB)", 0, MEDIUM),
+    ];
+    for (heading, min, max) in buckets {
+        let bucket: Vec<&&Found> = others
+            .iter()
+            .filter(|f| (min..max).contains(&f.size))
+            .collect();
+        if bucket.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n# {heading}");
+        for f in bucket {
+            let _ = writeln!(out, "# {:<40} {}", f.pattern(), format_size(f.size));
+        }
+    }
+    out
+}
+
+/// Format a byte count for humans, e.g. `312.4 MB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn scan_and_render_group_ignored_entries() {
+        let dir = std::env::temp_dir().join("worktree-link-test-init");
+        let _ = fs::remove_dir_all(&dir);
+        for sub in [
+            "node_modules/pkg",
+            "packages/web/node_modules",
+            "dist",
+            "src",
+        ] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("node_modules/pkg/index.js"), "x".repeat(2048)).unwrap();
+        fs::write(dir.join("packages/web/node_modules/a.js"), "").unwrap();
+        fs::write(dir.join("dist/app.js"), "bundle").unwrap();
+        fs::write(dir.join(".env"), "A=1").unwrap();
+        fs::write(dir.join(".env.example"), "A=").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join(".worktreelinks.local"), "").unwrap();
+        fs::write(
+            dir.join(".gitignore"),
+            "node_modules/\ndist/\n.env\n.worktreelinks.local\n",
+        )
+        .unwrap();
+        let status = Command::new("git")
+            .args(["init", "--quiet"])
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let found = scan(&dir).unwrap();
+        let paths: Vec<_> = found.iter().map(|f| f.path.to_str().unwrap()).collect();
+        assert_eq!(
+            paths,
+            vec![".env", "dist", "node_modules", "packages/web/node_modules"]
+        );
+        assert_eq!(found[2].size, 2048);
+
+        let rendered = render(&found);
+        assert!(
+            rendered.contains(
+                "\n# Node.js dependencies (2 found, 2.0 KB)\n/node_modules/\n/packages/web/node_modules/\n"
+            ),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains("\n# Environment files (1 found, 3 B)\n/.env\n"),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains("\n# Small (under 1 MB)\n# /dist/"),
+            "{rendered}"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn format_size_picks_a_unit() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+}
@@ -1,22 +1,139 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 use tracing::{debug, info, warn};
 
+use crate::template;
+
+/// How a matched source entry is brought into the target worktree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Create a symlink pointing at the source entry.
+    #[default]
+    Symlink,
+    /// Recursively copy the source entry so the target can diverge.
+    Copy,
+    /// Copy-on-write clone (`FICLONE` / `clonefile`): independent like a
+    /// copy but sharing extents until modified. Only works on CoW filesystems.
+    Reflink,
+    /// Hardlink each file; directories are recreated and their files
+    /// hardlinked. Source and target must be on the same device.
+    Hardlink,
+    /// Copy each file with `{{name}}` placeholders replaced by per-worktree
+    /// values (see [`template::Vars`]).
+    Template,
+    /// Create an empty, unshared directory mirroring the source directory's
+    /// permissions, optionally containing an empty `.keep` file.
+    Mkdir { keep: bool },
+}
+
+impl Strategy {
+    /// Look up a strategy by its name as used in `.worktreelinks` prefixes.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "symlink" => Some(Strategy::Symlink),
+            "copy" => Some(Strategy::Copy),
+            "reflink" => Some(Strategy::Reflink),
+            "hardlink" => Some(Strategy::Hardlink),
+            "template" => Some(Strategy::Template),
+            "mkdir" => Some(Strategy::Mkdir { keep: false }),
+            "mkdir-keep" => Some(Strategy::Mkdir { keep: true }),
+            _ => None,
+        }
+    }
+
+    /// Parse a `.worktreelinks` line prefix (without `:`) into a fallback
+    /// chain, e.g. `reflink,copy`. Returns `None` unless every element is a
+    /// known strategy, so ordinary patterns containing `:` are left alone.
+    pub fn parse_chain(prefix: &str) -> Option<Vec<Self>> {
+        prefix
+            .split(',')
+            .map(|name| Strategy::from_name(name.trim()))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Strategy::Symlink => "symlink",
+            Strategy::Copy => "copy",
+            Strategy::Reflink => "reflink",
+            Strategy::Hardlink => "hardlink",
+            Strategy::Template => "template",
+            Strategy::Mkdir { keep: false } => "mkdir",
+            Strategy::Mkdir { keep: true } => "mkdir-keep",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What to do when the target path already exists and is not already linked
+/// to the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Leave the existing entry alone (unless `--force` is given).
+    #[default]
+    Skip,
+    /// Replace the existing entry.
+    Overwrite,
+    /// Move the existing entry aside to `<name>.bak` first.
+    Backup,
+}
+
+/// Options shared by every strategy when materializing a target.
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    /// Overwrite existing files/symlinks.
+    pub force: bool,
+    /// Report what would happen without touching the filesystem.
+    pub dry_run: bool,
+    /// Write symlinks as the shortest path relative to the link's directory
+    /// instead of the absolute source path.
+    pub relative: bool,
+    /// Values substituted by the `template` strategy.
+    pub vars: template::Vars,
+}
+
 /// Describes what happened when attempting to create a link.
 #[derive(Debug, PartialEq)]
 pub enum LinkAction {
     Created { source: PathBuf, target: PathBuf },
     Skipped { target: PathBuf, reason: String },
     Overwritten { source: PathBuf, target: PathBuf },
+    Copied { source: PathBuf, target: PathBuf },
+    CopySkipped { target: PathBuf, reason: String },
+    Reflinked { source: PathBuf, target: PathBuf },
+    Hardlinked { source: PathBuf, target: PathBuf },
+    Rendered { source: PathBuf, target: PathBuf },
+    DirCreated { source: PathBuf, target: PathBuf },
+    Adopted { source: PathBuf, target: PathBuf },
+    BackedUp { target: PathBuf, backup: PathBuf },
 }
 
 /// Describes what happened when attempting to unlink.
 #[derive(Debug, PartialEq)]
 pub enum UnlinkAction {
     Removed(PathBuf),
-    Skipped { target: PathBuf, reason: String },
+    /// The link was replaced by an independent copy of its content.
+    Materialized(PathBuf),
+    Skipped {
+        target: PathBuf,
+        reason: String,
+    },
+}
+
+impl UnlinkAction {
+    pub fn path(&self) -> &Path {
+        match self {
+            UnlinkAction::Removed(p)
+            | UnlinkAction::Materialized(p)
+            | UnlinkAction::Skipped { target: p, .. } => p,
+        }
+    }
 }
 
 impl std::fmt::Display for LinkAction {
@@ -49,6 +166,72 @@ impl std::fmt::Display for LinkAction {
                     source.display()
                 )
             }
+            LinkAction::Copied { source, target } => {
+                write!(
+                    f,
+                    "{} {} <- {}",
+                    "COPY".blue().bold(),
+                    target.display(),
+                    source.display()
+                )
+            }
+            LinkAction::CopySkipped { target, reason } => {
+                write!(
+                    f,
+                    "{} {} ({})",
+                    "SKIP COPY".yellow().bold(),
+                    target.display(),
+                    reason
+                )
+            }
+            LinkAction::Reflinked { source, target } => {
+                write!(
+                    f,
+                    "{} {} <- {}",
+                    "REFLINK".blue().bold(),
+                    target.display(),
+                    source.display()
+                )
+            }
+            LinkAction::Hardlinked { source, target } => {
+                write!(
+                    f,
+                    "{} {} => {}",
+                    "HARDLINK".green().bold(),
+                    target.display(),
+                    source.display()
+                )
+            }
+            LinkAction::Rendered { source, target } => {
+                write!(
+                    f,
+                    "{} {} <- {}",
+                    "RENDER".cyan().bold(),
+                    target.display(),
+                    source.display()
+                )
+            }
+            LinkAction::DirCreated { target, .. } => {
+                write!(f, "{} {}", "MKDIR".green().bold(), target.display())
+            }
+            LinkAction::Adopted { source, target } => {
+                write!(
+                    f,
+                    "{} {} -> {}",
+                    "ADOPT".green().bold(),
+                    target.display(),
+                    source.display()
+                )
+            }
+            LinkAction::BackedUp { target, backup } => {
+                write!(
+                    f,
+                    "{} {} -> {}",
+                    "BACKUP".magenta().bold(),
+                    target.display(),
+                    backup.display()
+                )
+            }
         }
     }
 }
@@ -59,6 +242,9 @@ impl std::fmt::Display for UnlinkAction {
             UnlinkAction::Removed(path) => {
                 write!(f, "{} {}", "UNLINK".red().bold(), path.display())
             }
+            UnlinkAction::Materialized(path) => {
+                write!(f, "{} {}", "DETACH".blue().bold(), path.display())
+            }
             UnlinkAction::Skipped { target, reason } => {
                 write!(
                     f,
@@ -75,6 +261,8 @@ impl std::fmt::Display for UnlinkAction {
 /// Create a symlink from `source_path` to `target_path`.
 ///
 /// `source_path` must be an absolute path (under the canonical source root).
+/// With [`LinkOptions::relative`] the link stores the path from the link's
+/// directory to `source_path` instead, so it survives moving both trees.
 /// We intentionally do NOT call `fs::canonicalize` on it so that symlinks
 /// within the source tree are preserved as-is, keeping the link/unlink
 /// round-trip consistent.
@@ -83,9 +271,9 @@ impl std::fmt::Display for UnlinkAction {
 pub fn create_link(
     source_path: &Path,
     target_path: &Path,
-    force: bool,
-    dry_run: bool,
+    opts: &LinkOptions,
 ) -> Result<LinkAction> {
+    let LinkOptions { force, dry_run, .. } = *opts;
     anyhow::ensure!(
         source_path.is_absolute(),
         "source_path must be absolute: {}",
@@ -119,7 +307,7 @@ pub fn create_link(
             remove_entry(target_path)
                 .with_context(|| format!("Failed to remove: {}", target_path.display()))?;
             create_parent_dirs(target_path)?;
-            symlink(source_path, target_path)?;
+            symlink(&link_dest(source_path, target_path, opts), target_path)?;
         }
 
         if dry_run {
@@ -145,7 +333,7 @@ pub fn create_link(
 
     if !dry_run {
         create_parent_dirs(target_path)?;
-        symlink(source_path, target_path)?;
+        symlink(&link_dest(source_path, target_path, opts), target_path)?;
     }
 
     if dry_run {
@@ -159,30 +347,686 @@ pub fn create_link(
     })
 }
 
-/// Walk `target_dir` and remove any symlinks that point into `source_dir`.
+/// Recursively copy `source_path` to `target_path`.
+///
+/// Unlike [`create_link`], the result is fully independent of the source, so
+/// edits in the target worktree never leak back into the main one. Symlinks
+/// inside the copied tree are recreated as-is rather than followed.
+pub fn create_copy(
+    source_path: &Path,
+    target_path: &Path,
+    opts: &LinkOptions,
+) -> Result<LinkAction> {
+    let LinkOptions { force, dry_run, .. } = *opts;
+    debug!(
+        "create_copy: {} <- {}",
+        target_path.display(),
+        source_path.display()
+    );
+
+    let exists = target_path.exists() || target_path.is_symlink();
+    if exists && !force {
+        return Ok(LinkAction::CopySkipped {
+            target: target_path.to_path_buf(),
+            reason: "already exists (use --force to overwrite)".into(),
+        });
+    }
+
+    // Same guard as create_link: never write (or delete) through a symlinked
+    // parent, which would touch the source worktree instead of the target.
+    if has_symlink_parent(target_path) {
+        return Ok(LinkAction::CopySkipped {
+            target: target_path.to_path_buf(),
+            reason: "parent directory is a symlink (remove it first)".into(),
+        });
+    }
+
+    if !dry_run {
+        if exists {
+            remove_entry(target_path)
+                .with_context(|| format!("Failed to remove: {}", target_path.display()))?;
+        }
+        create_parent_dirs(target_path)?;
+        copy_recursive(source_path, target_path, &copy_file).with_context(|| {
+            format!(
+                "Failed to copy: {} -> {}",
+                source_path.display(),
+                target_path.display()
+            )
+        })?;
+    }
+
+    if dry_run {
+        info!("[dry-run] would copy: {}", target_path.display());
+    } else {
+        info!("copied: {}", target_path.display());
+    }
+    Ok(LinkAction::Copied {
+        source: source_path.to_path_buf(),
+        target: target_path.to_path_buf(),
+    })
+}
+
+/// Create an empty directory at `target_path` mirroring the permissions of
+/// the source directory, for paths that must exist but must not be shared
+/// (pid files, logs, caches).
+///
+/// An existing real directory is always left alone, even with `--force`, since
+/// it may hold this worktree's own data. Anything else in the way (typically
+/// a symlink from an earlier run) is replaced only with `--force`.
+pub fn create_dir(
+    source_path: &Path,
+    target_path: &Path,
+    keep: bool,
+    opts: &LinkOptions,
+) -> Result<LinkAction> {
+    let LinkOptions { force, dry_run, .. } = *opts;
+    debug!("create_dir: {}", target_path.display());
+
+    if !source_path.is_dir() {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "source is not a directory".into(),
+        });
+    }
+
+    let is_real_dir = fs::symlink_metadata(target_path).is_ok_and(|m| m.is_dir());
+    if is_real_dir {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "directory already exists".into(),
+        });
+    }
+
+    let exists = target_path.exists() || target_path.is_symlink();
+    if exists && !force {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "already exists (use --force to overwrite)".into(),
+        });
+    }
+
+    if has_symlink_parent(target_path) {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "parent directory is a symlink (remove it first)".into(),
+        });
+    }
+
+    if !dry_run {
+        if exists {
+            remove_entry(target_path)
+                .with_context(|| format!("Failed to remove: {}", target_path.display()))?;
+        }
+        create_parent_dirs(target_path)?;
+        make_empty_dir(source_path, target_path, keep)
+            .with_context(|| format!("Failed to create directory: {}", target_path.display()))?;
+    }
+
+    if dry_run {
+        info!(
+            "[dry-run] would create directory: {}",
+            target_path.display()
+        );
+    } else {
+        info!("created directory: {}", target_path.display());
+    }
+    Ok(LinkAction::DirCreated {
+        source: source_path.to_path_buf(),
+        target: target_path.to_path_buf(),
+    })
+}
+
+/// Remove a directory created by [`create_dir`], but only while it is still
+/// empty (apart from the `.keep` file we may have put there). Returns `None`
+/// if there is no real directory at `target_path`.
+pub fn remove_empty_dir(target_path: &Path, dry_run: bool) -> Result<Option<UnlinkAction>> {
+    if !fs::symlink_metadata(target_path).is_ok_and(|m| m.is_dir()) {
+        return Ok(None);
+    }
+
+    let keep = target_path.join(KEEP_FILE);
+    let mut has_keep = false;
+    for entry in fs::read_dir(target_path)
+        .with_context(|| format!("Failed to read dir: {}", target_path.display()))?
+    {
+        let entry = entry?;
+        let is_empty_keep = entry.file_name() == KEEP_FILE
+            && entry.metadata().is_ok_and(|m| m.is_file() && m.len() == 0);
+        if !is_empty_keep {
+            return Ok(Some(UnlinkAction::Skipped {
+                target: target_path.to_path_buf(),
+                reason: "directory not empty".into(),
+            }));
+        }
+        has_keep = true;
+    }
+
+    if dry_run {
+        info!(
+            "[dry-run] would remove directory: {}",
+            target_path.display()
+        );
+    } else {
+        if has_keep {
+            fs::remove_file(&keep)
+                .with_context(|| format!("Failed to remove: {}", keep.display()))?;
+        }
+        fs::remove_dir(target_path)
+            .with_context(|| format!("Failed to remove: {}", target_path.display()))?;
+        info!("removed directory: {}", target_path.display());
+    }
+    Ok(Some(UnlinkAction::Removed(target_path.to_path_buf())))
+}
+
+/// Materialize `source_path` at `target_path` using the first strategy in
+/// `strategies` that the filesystem supports.
+///
+/// Strategies that can fail for environmental reasons (e.g. reflink on a
+/// non-CoW filesystem or across devices) fall through to the next one; the
+/// returned action names the strategy that actually succeeded. A chain of a
+/// single strategy behaves exactly like calling that strategy directly.
+/// It is an error if no strategy works; an existing target (with `--force`)
+/// is only replaced once one has. In dry-run mode nothing is probed, so the
+/// first strategy is reported.
+pub fn create_with(
+    strategies: &[Strategy],
+    source_path: &Path,
+    target_path: &Path,
+    opts: &LinkOptions,
+) -> Result<LinkAction> {
+    match strategies {
+        [] | [Strategy::Symlink] => return create_link(source_path, target_path, opts),
+        [Strategy::Copy] => return create_copy(source_path, target_path, opts),
+        [Strategy::Mkdir { keep }] => return create_dir(source_path, target_path, *keep, opts),
+        _ => {}
+    }
+    try_strategies(
+        strategies,
+        source_path,
+        target_path,
+        opts,
+        &|strategy, src, dst| materialize(strategy, src, dst, opts),
+    )
+}
+
+/// Materialize `source` at `target` with a single non-symlink strategy.
+fn materialize(
+    strategy: Strategy,
+    source: &Path,
+    target: &Path,
+    opts: &LinkOptions,
+) -> io::Result<()> {
+    match strategy {
+        Strategy::Symlink => unreachable!("symlinks are created by try_strategies"),
+        Strategy::Copy => copy_recursive(source, target, &copy_file),
+        Strategy::Reflink => copy_recursive(source, target, &reflink_file),
+        Strategy::Hardlink => ensure_same_device(source, target)
+            .and_then(|()| copy_recursive(source, target, &hardlink_file)),
+        Strategy::Template => copy_recursive(source, target, &|src, dst| {
+            render_file(src, dst, &opts.vars)
+        }),
+        Strategy::Mkdir { keep } => make_empty_dir(source, target, keep),
+    }
+}
+
+/// The fallback loop of [`create_with`], with the non-symlink strategies
+/// applied by `apply` so tests can make one fail on demand.
+fn try_strategies(
+    strategies: &[Strategy],
+    source_path: &Path,
+    target_path: &Path,
+    opts: &LinkOptions,
+    apply: &dyn Fn(Strategy, &Path, &Path) -> io::Result<()>,
+) -> Result<LinkAction> {
+    let LinkOptions { force, dry_run, .. } = *opts;
+
+    anyhow::ensure!(
+        source_path.is_absolute(),
+        "source_path must be absolute: {}",
+        source_path.display()
+    );
+
+    debug!(
+        "create_with [{}]: {} <- {}",
+        strategies
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        target_path.display(),
+        source_path.display()
+    );
+
+    let exists = target_path.exists() || target_path.is_symlink();
+    if exists && !force {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "already exists (use --force to overwrite)".into(),
+        });
+    }
+
+    if has_symlink_parent(target_path) {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "parent directory is a symlink (remove it first)".into(),
+        });
+    }
+
+    if dry_run {
+        info!(
+            "[dry-run] would {}: {}",
+            strategies[0],
+            target_path.display()
+        );
+        return Ok(strategy_action(
+            strategies[0],
+            source_path,
+            target_path,
+            exists,
+        ));
+    }
+
+    create_parent_dirs(target_path)?;
+
+    // Build the new entry next to the target and only replace the existing
+    // one once a strategy has succeeded, so a forced run never loses it.
+    let tmp = sibling(target_path, "new");
+    if tmp.exists() || tmp.is_symlink() {
+        remove_entry(&tmp).with_context(|| format!("Failed to clean up: {}", tmp.display()))?;
+    }
+    let mut failures = Vec::new();
+    for &strategy in strategies {
+        let result = match strategy {
+            Strategy::Symlink => {
+                symlink(&link_dest(source_path, target_path, opts), &tmp)?;
+                Ok(())
+            }
+            _ => apply(strategy, source_path, &tmp),
+        };
+
+        match result {
+            Ok(()) => {
+                if exists {
+                    replace_entry(&tmp, target_path)?;
+                } else {
+                    fs::rename(&tmp, target_path).with_context(|| {
+                        format!("Failed to move into place: {}", target_path.display())
+                    })?;
+                }
+                info!("{strategy}: {}", target_path.display());
+                return Ok(strategy_action(strategy, source_path, target_path, exists));
+            }
+            Err(e) => {
+                // Discard any partially materialized tree.
+                if tmp.exists() || tmp.is_symlink() {
+                    remove_entry(&tmp)
+                        .with_context(|| format!("Failed to clean up: {}", tmp.display()))?;
+                }
+                if !is_unsupported(&e) {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Failed to {strategy}: {} -> {}",
+                            source_path.display(),
+                            target_path.display()
+                        )
+                    });
+                }
+                debug!("{strategy} unsupported for {}: {e}", target_path.display());
+                failures.push(describe_unsupported(strategy, &e));
+            }
+        }
+    }
+
+    match failures.as_slice() {
+        [only] => bail!("Failed to create {}: {only}", target_path.display()),
+        _ => bail!(
+            "Failed to create {}: no strategy succeeded ({})",
+            target_path.display(),
+            failures.join("; ")
+        ),
+    }
+}
+
+/// Move the freshly built `tmp` over the existing entry at `target`. The old
+/// entry is moved aside first and put back if `tmp` can't take its place.
+fn replace_entry(tmp: &Path, target: &Path) -> Result<()> {
+    let aside = sibling(target, "old");
+    fs::rename(target, &aside)
+        .with_context(|| format!("Failed to move aside: {}", target.display()))?;
+    if let Err(e) = fs::rename(tmp, target) {
+        let _ = fs::rename(&aside, target);
+        return Err(e).with_context(|| format!("Failed to replace: {}", target.display()));
+    }
+    remove_entry(&aside).with_context(|| format!("Failed to remove: {}", aside.display()))
+}
+
+/// Human-readable reason for a strategy that could not be used.
+fn describe_unsupported(strategy: Strategy, err: &io::Error) -> String {
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(libc::EXDEV) {
+        return format!("{strategy}: source and target are on different devices");
+    }
+    format!("{strategy}: {err}")
+}
+
+/// What to store in a symlink at `target_path` that points at `source_path`.
+fn link_dest(source_path: &Path, target_path: &Path, opts: &LinkOptions) -> PathBuf {
+    match target_path.parent() {
+        Some(dir) if opts.relative => relative_path(dir, source_path),
+        _ => source_path.to_path_buf(),
+    }
+}
+
+/// Compute the shortest relative path from directory `from_dir` to `to`.
+/// Both paths must be absolute and free of `.`/`..` components.
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut rel = PathBuf::new();
+    for _ in common..from.len() {
+        rel.push("..");
+    }
+    for component in &to[common..] {
+        rel.push(component);
+    }
+    if rel.as_os_str().is_empty() {
+        rel.push(".");
+    }
+    rel
+}
+
+/// The action reported when `strategy` materialized `target`.
+fn strategy_action(strategy: Strategy, source: &Path, target: &Path, existed: bool) -> LinkAction {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+    match strategy {
+        Strategy::Symlink if existed => LinkAction::Overwritten { source, target },
+        Strategy::Symlink => LinkAction::Created { source, target },
+        Strategy::Copy => LinkAction::Copied { source, target },
+        Strategy::Reflink => LinkAction::Reflinked { source, target },
+        Strategy::Hardlink => LinkAction::Hardlinked { source, target },
+        Strategy::Template => LinkAction::Rendered { source, target },
+        Strategy::Mkdir { .. } => LinkAction::DirCreated { source, target },
+    }
+}
+
+/// Whether an I/O error means "this strategy can't work here" (so the next
+/// strategy in the chain should be tried) rather than a genuine failure.
+fn is_unsupported(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        // EOPNOTSUPP and ENOTSUP are the same value on Linux but not on macOS.
+        let unsupported = [
+            libc::EOPNOTSUPP,
+            libc::ENOTSUP,
+            libc::EXDEV,
+            libc::EINVAL,
+            libc::ENOTTY,
+            libc::ENOSYS,
+        ];
+        err.raw_os_error()
+            .is_some_and(|code| unsupported.contains(&code))
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Move the real entry at `rel` in the `target` worktree into the same place
+/// in `source`, then replace it with a symlink via [`create_link`].
+///
+/// An existing source entry is only replaced with `--force`. Entries that
+/// are already symlinks are skipped. `rel` must name an entry below the
+/// worktree root outside `.git`, so neither root is ever moved or removed.
+pub fn adopt(source: &Path, target: &Path, rel: &Path, opts: &LinkOptions) -> Result<LinkAction> {
+    let LinkOptions { force, dry_run, .. } = *opts;
+    let below_root = rel
+        .components()
+        .all(|c| matches!(c, Component::Normal(name) if name != ".git"));
+    if rel.as_os_str().is_empty() || !below_root {
+        bail!(
+            "Cannot adopt the worktree root or anything in .git: {}",
+            target.join(rel).display()
+        );
+    }
+    let target_path = &target.join(rel);
+    let source_path = &source.join(rel);
+    debug!(
+        "adopt: {} -> {}",
+        target_path.display(),
+        source_path.display()
+    );
+
+    let meta = match fs::symlink_metadata(target_path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(LinkAction::Skipped {
+                target: target_path.to_path_buf(),
+                reason: "does not exist".into(),
+            });
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to stat: {}", target_path.display()))
+        }
+    };
+    if meta.file_type().is_symlink() {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "already a symlink".into(),
+        });
+    }
+    if has_symlink_parent(target_path) {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "parent directory is a symlink (remove it first)".into(),
+        });
+    }
+
+    let source_exists = source_path.exists() || source_path.is_symlink();
+    if source_exists && !force {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "already exists in source (use --force to replace)".into(),
+        });
+    }
+    if has_symlink_parent(source_path) {
+        return Ok(LinkAction::Skipped {
+            target: target_path.to_path_buf(),
+            reason: "source parent directory is a symlink".into(),
+        });
+    }
+
+    if dry_run {
+        info!("[dry-run] would adopt: {}", target_path.display());
+    } else {
+        create_parent_dirs(source_path)?;
+        // Move the replaced source entry aside rather than deleting it, so it
+        // can be restored if the move fails.
+        let aside = source_exists.then(|| sibling(source_path, "old"));
+        if let Some(aside) = &aside {
+            fs::rename(source_path, aside)
+                .with_context(|| format!("Failed to move aside: {}", source_path.display()))?;
+        }
+        if let Err(e) = move_entry(target_path, source_path) {
+            if let Some(aside) = &aside {
+                if !source_path.exists() && !source_path.is_symlink() {
+                    let _ = fs::rename(aside, source_path);
+                }
+            }
+            return Err(e).with_context(|| {
+                format!(
+                    "Failed to move {} to {}",
+                    target_path.display(),
+                    source_path.display()
+                )
+            });
+        }
+        if let Some(aside) = &aside {
+            remove_entry(aside)
+                .with_context(|| format!("Failed to remove: {}", aside.display()))?;
+        }
+        let link_opts = LinkOptions {
+            force: false,
+            ..opts.clone()
+        };
+        create_link(source_path, target_path, &link_opts)?;
+        info!("adopted: {}", target_path.display());
+    }
+
+    Ok(LinkAction::Adopted {
+        source: source_path.to_path_buf(),
+        target: target_path.to_path_buf(),
+    })
+}
+
+/// Move an existing `target_path` aside to `<name>.bak` (or `<name>.bak.N`
+/// if that is taken) so the entry for `source_path` can be created there.
+///
+/// Nothing is moved if the target does not exist, is already a link to
+/// `source_path`, or sits below a symlinked directory.
+pub fn backup_existing(
+    source_path: &Path,
+    target_path: &Path,
+    dry_run: bool,
+) -> Result<Option<LinkAction>> {
+    let Ok(meta) = fs::symlink_metadata(target_path) else {
+        return Ok(None);
+    };
+    let already_linked = if meta.is_symlink() {
+        fs::canonicalize(target_path).ok() == fs::canonicalize(source_path).ok()
+    } else {
+        meta.is_file() && is_hardlink_of(&meta, source_path)
+    };
+    if already_linked || has_symlink_parent(target_path) {
+        return Ok(None);
+    }
+
+    let name = target_path.file_name().unwrap_or_default().to_os_string();
+    let backup = (0..)
+        .map(|n| {
+            let mut backup_name = name.clone();
+            backup_name.push(if n == 0 {
+                ".bak".to_string()
+            } else {
+                format!(".bak.{n}")
+            });
+            target_path.with_file_name(backup_name)
+        })
+        .find(|candidate| !candidate.exists() && !candidate.is_symlink())
+        .expect("unbounded range yields a free name");
+
+    if dry_run {
+        info!("[dry-run] would back up: {}", target_path.display());
+    } else {
+        fs::rename(target_path, &backup)
+            .with_context(|| format!("Failed to back up: {}", target_path.display()))?;
+        info!(
+            "backed up: {} -> {}",
+            target_path.display(),
+            backup.display()
+        );
+    }
+    Ok(Some(LinkAction::BackedUp {
+        target: target_path.to_path_buf(),
+        backup,
+    }))
+}
+
+/// Walk `target_dir` and remove any symlinks that point into one of
+/// `source_dirs` (the main source and any `@source` trees).
 ///
 /// This walks the target side (not the source), so it also catches stale
 /// symlinks whose source-side originals have been deleted or renamed.
 /// Errors on individual entries are logged as warnings and skipped so that
 /// the walk continues (best-effort).
+///
+/// Hardlinks carry no pointer to their source, so they are only recognized
+/// at the same relative path in a source, or at one of the `mapped`
+/// destinations: pairs of a path relative to `target_dir` and the source
+/// entry that a ` => ` mapping places there.
 pub fn unlink_targets(
-    source_dir: &Path,
+    source_dirs: &[PathBuf],
+    mapped: &[(PathBuf, PathBuf)],
     target_dir: &Path,
     dry_run: bool,
 ) -> Result<Vec<UnlinkAction>> {
-    // Canonicalize source_dir so the starts_with comparison works correctly
-    // against fully-resolved link destinations.
-    let canonical_source = fs::canonicalize(source_dir).with_context(|| {
-        format!(
-            "Failed to canonicalize source dir: {}",
-            source_dir.display()
-        )
-    })?;
+    for_each_link(source_dirs, mapped, target_dir, &mut |entry_path| {
+        Some(remove_link(entry_path, dry_run))
+    })
+}
+
+/// Walk `target_dir` like [`unlink_targets`], but replace each link into
+/// `source_dirs` with an independent copy of what it points to.
+///
+/// If `only` is non-empty, only links at or below one of those paths are
+/// detached. Each copy is fully built next to the link before it takes the
+/// link's place, so a failure midway leaves the link intact.
+pub fn materialize_targets(
+    source_dirs: &[PathBuf],
+    mapped: &[(PathBuf, PathBuf)],
+    target_dir: &Path,
+    only: &[PathBuf],
+    dry_run: bool,
+) -> Result<Vec<UnlinkAction>> {
+    for_each_link(source_dirs, mapped, target_dir, &mut |entry_path| {
+        if !only.is_empty() && !only.iter().any(|p| entry_path.starts_with(p)) {
+            return None;
+        }
+        Some(materialize_link(entry_path, dry_run))
+    })
+}
+
+/// Find every symlink (and hardlink) in `target_dir` that points into one
+/// of `source_dirs` and let `handle` decide what to do with it. Results are
+/// sorted by path.
+fn for_each_link(
+    source_dirs: &[PathBuf],
+    mapped: &[(PathBuf, PathBuf)],
+    target_dir: &Path,
+    handle: &mut dyn FnMut(PathBuf) -> Option<UnlinkAction>,
+) -> Result<Vec<UnlinkAction>> {
+    // Canonicalize the source dirs so the starts_with comparison works
+    // correctly against fully-resolved link destinations.
+    let canonical_sources = source_dirs
+        .iter()
+        .map(|dir| {
+            fs::canonicalize(dir)
+                .with_context(|| format!("Failed to canonicalize source dir: {}", dir.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let mut actions = Vec::new();
 
-    walk_symlinks(target_dir, &mut |entry_path| {
-        if !entry_path.is_symlink() {
+    walk_links(target_dir, &mut |entry_path, meta| {
+        // Regular files can only be ours if they are hardlinks of the file at
+        // the same relative path in a source, or at a mapped destination.
+        if meta.is_file() {
+            let is_ours = entry_path.strip_prefix(target_dir).is_ok_and(|rel| {
+                let at_mapped = |(dest, source): &(PathBuf, PathBuf)| {
+                    rel.strip_prefix(dest).is_ok_and(|rest| {
+                        let file = if rest.as_os_str().is_empty() {
+                            source.clone()
+                        } else {
+                            source.join(rest)
+                        };
+                        is_hardlink_of(meta, &file)
+                    })
+                };
+                canonical_sources
+                    .iter()
+                    .any(|source| is_hardlink_of(meta, &source.join(rel)))
+                    || mapped.iter().any(at_mapped)
+            });
+            if is_ours {
+                actions.extend(handle(entry_path));
+            }
             return Ok(());
         }
 
@@ -199,7 +1043,7 @@ pub fn unlink_targets(
         };
 
         // Resolve relative symlink targets to absolute paths for comparison.
-        // fs::read_link can return relative paths, while source_dir is canonical.
+        // fs::read_link can return relative paths, while the sources are canonical.
         let resolved = if link_dest.is_absolute() {
             link_dest
         } else {
@@ -214,48 +1058,133 @@ pub fn unlink_targets(
         // we canonicalize the deepest existing ancestor and append the remainder.
         let resolved = canonicalize_with_ancestor_fallback(&resolved);
 
-        // Only remove symlinks that point into the source directory
-        if !resolved.starts_with(&canonical_source) {
-            return Ok(());
+        // Only handle symlinks that point into a source directory
+        if !canonical_sources.iter().any(|s| resolved.starts_with(s)) {
+            return Ok(());
+        }
+
+        actions.extend(handle(entry_path));
+        Ok(())
+    })?;
+
+    actions.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(actions)
+}
+
+/// Replace the link at `entry_path` with a copy of the content it refers to.
+fn materialize_link(entry_path: PathBuf, dry_run: bool) -> UnlinkAction {
+    // Follows the symlink; for hardlinks this is the file itself.
+    let content = match fs::canonicalize(&entry_path) {
+        Ok(content) => content,
+        Err(e) => {
+            return UnlinkAction::Skipped {
+                target: entry_path,
+                reason: format!("cannot resolve link: {e}"),
+            };
+        }
+    };
+
+    if dry_run {
+        info!("[dry-run] would detach: {}", entry_path.display());
+        return UnlinkAction::Materialized(entry_path);
+    }
+
+    let tmp = sibling(&entry_path, "detach");
+    let result =
+        copy_recursive(&content, &tmp, &copy_file).and_then(|()| swap_in(&tmp, &entry_path));
+
+    match result {
+        Ok(()) => {
+            info!("detached: {}", entry_path.display());
+            UnlinkAction::Materialized(entry_path)
         }
-
-        if !dry_run {
-            if let Err(e) = remove_entry(&entry_path) {
-                warn!("Failed to remove {}: {e}", entry_path.display());
-                actions.push(UnlinkAction::Skipped {
-                    target: entry_path,
-                    reason: format!("removal failed: {e}"),
-                });
-                return Ok(());
+        Err(e) => {
+            warn!("Failed to detach {}: {e}", entry_path.display());
+            if tmp.exists() {
+                let _ = remove_entry(&tmp);
+            }
+            UnlinkAction::Skipped {
+                target: entry_path,
+                reason: format!("detach failed: {e}"),
             }
         }
+    }
+}
 
-        if dry_run {
-            info!("[dry-run] would unlink: {}", entry_path.display());
-        } else {
-            info!("unlinked: {}", entry_path.display());
+/// A hidden temporary name next to `path`, e.g. `.name.wtl-detach-<pid>`.
+fn sibling(path: &Path, purpose: &str) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".wtl-{purpose}-{}", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Move `tmp` over the link at `entry_path`.
+///
+/// rename(2) atomically replaces a file, but cannot replace a non-directory
+/// with a directory. For a directory the link is moved aside first and put
+/// back if the copy can't take its place, so the link is never lost.
+fn swap_in(tmp: &Path, entry_path: &Path) -> io::Result<()> {
+    if !fs::symlink_metadata(tmp)?.is_dir() {
+        return fs::rename(tmp, entry_path);
+    }
+    let aside = sibling(entry_path, "link");
+    fs::rename(entry_path, &aside)?;
+    if let Err(e) = fs::rename(tmp, entry_path) {
+        let _ = fs::rename(&aside, entry_path);
+        return Err(e);
+    }
+    fs::remove_file(&aside)
+}
+
+/// Remove a single link found by [`unlink_targets`], reporting failures as skips.
+fn remove_link(entry_path: PathBuf, dry_run: bool) -> UnlinkAction {
+    if !dry_run {
+        if let Err(e) = remove_entry(&entry_path) {
+            warn!("Failed to remove {}: {e}", entry_path.display());
+            return UnlinkAction::Skipped {
+                target: entry_path,
+                reason: format!("removal failed: {e}"),
+            };
         }
-        actions.push(UnlinkAction::Removed(entry_path));
-        Ok(())
-    })?;
+    }
 
-    actions.sort_by(|a, b| {
-        let path_a = match a {
-            UnlinkAction::Removed(p) | UnlinkAction::Skipped { target: p, .. } => p,
-        };
-        let path_b = match b {
-            UnlinkAction::Removed(p) | UnlinkAction::Skipped { target: p, .. } => p,
-        };
-        path_a.cmp(path_b)
-    });
+    if dry_run {
+        info!("[dry-run] would unlink: {}", entry_path.display());
+    } else {
+        info!("unlinked: {}", entry_path.display());
+    }
+    UnlinkAction::Removed(entry_path)
+}
 
-    Ok(actions)
+/// Whether `meta` (of a target-side regular file) refers to the same inode on
+/// the same device as `source_file`.
+#[cfg(unix)]
+fn is_hardlink_of(meta: &fs::Metadata, source_file: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    // A file with a single link cannot be shared with the source.
+    if meta.nlink() < 2 {
+        return false;
+    }
+    fs::symlink_metadata(source_file)
+        .is_ok_and(|src| src.is_file() && src.dev() == meta.dev() && src.ino() == meta.ino())
+}
+
+#[cfg(not(unix))]
+fn is_hardlink_of(_meta: &fs::Metadata, _source_file: &Path) -> bool {
+    false
 }
 
-/// Recursively walk a directory, calling `visitor` on each symlink found.
-/// Does not follow symlinks (so symlinked directories are visited but not descended into).
+/// Recursively walk a directory, calling `visitor` on each symlink and regular
+/// file found. Does not follow symlinks (so symlinked directories are visited
+/// but not descended into).
 /// Errors on individual entries are warned and skipped (best-effort).
-fn walk_symlinks(dir: &Path, visitor: &mut dyn FnMut(PathBuf) -> Result<()>) -> Result<()> {
+fn walk_links(
+    dir: &Path,
+    visitor: &mut dyn FnMut(PathBuf, &fs::Metadata) -> Result<()>,
+) -> Result<()> {
     let entries = match fs::read_dir(dir) {
         Ok(entries) => entries,
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
@@ -283,14 +1212,14 @@ fn walk_symlinks(dir: &Path, visitor: &mut dyn FnMut(PathBuf) -> Result<()>) ->
             }
         };
 
-        if meta.file_type().is_symlink() {
-            visitor(path)?;
+        if meta.file_type().is_symlink() || meta.is_file() {
+            visitor(path, &meta)?;
         } else if meta.is_dir() {
             // Skip .git to avoid damaging repository internals.
             if path.file_name().is_some_and(|n| n == ".git") {
                 continue;
             }
-            walk_symlinks(&path, visitor)?;
+            walk_links(&path, visitor)?;
         }
     }
 
@@ -377,6 +1306,150 @@ fn create_parent_dirs(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Recreate a file, directory tree, or symlink without following symlinks,
+/// using `file_op` to materialize each regular file.
+fn copy_recursive(
+    source: &Path,
+    target: &Path,
+    file_op: &dyn Fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let meta = fs::symlink_metadata(source)?;
+
+    if meta.file_type().is_symlink() {
+        let dest = fs::read_link(source)?;
+        raw_symlink(&dest, target)?;
+    } else if meta.is_dir() {
+        fs::create_dir_all(target)?;
+        fs::set_permissions(target, meta.permissions())?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()), file_op)?;
+        }
+    } else {
+        file_op(source, target)?;
+    }
+    Ok(())
+}
+
+fn copy_file(source: &Path, target: &Path) -> io::Result<()> {
+    fs::copy(source, target).map(|_| ())
+}
+
+/// Render a text file through the template variables, keeping its permissions.
+fn render_file(source: &Path, target: &Path, vars: &template::Vars) -> io::Result<()> {
+    let content = fs::read_to_string(source)?;
+    let rendered = vars.render(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", source.display()),
+        )
+    })?;
+    fs::write(target, rendered)?;
+    fs::set_permissions(target, fs::metadata(source)?.permissions())
+}
+
+/// Rename `from` to `to`, falling back to copy-and-delete across devices.
+fn move_entry(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if is_unsupported(&e) => {
+            if let Err(e) = copy_recursive(from, to, &copy_file) {
+                // Don't leave a partial copy behind.
+                if to.exists() || to.is_symlink() {
+                    let _ = remove_entry(to);
+                }
+                return Err(e);
+            }
+            if fs::symlink_metadata(from)?.is_dir() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+/// Name of the placeholder file created by `mkdir-keep`.
+const KEEP_FILE: &str = ".keep";
+
+fn make_empty_dir(source: &Path, target: &Path, keep: bool) -> io::Result<()> {
+    fs::create_dir(target)?;
+    fs::set_permissions(target, fs::metadata(source)?.permissions())?;
+    if keep {
+        fs::File::create(target.join(KEEP_FILE))?;
+    }
+    Ok(())
+}
+
+fn hardlink_file(source: &Path, target: &Path) -> io::Result<()> {
+    fs::hard_link(source, target)
+}
+
+/// Fail with `EXDEV` up front if `target`'s parent is on a different device
+/// than `source`, instead of half-populating a directory before `link(2)`
+/// reports it.
+#[cfg(unix)]
+fn ensure_same_device(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(parent) = target.parent() else {
+        return Ok(());
+    };
+    let source_dev = fs::symlink_metadata(source)?.dev();
+    let target_dev = fs::metadata(parent)?.dev();
+    if source_dev != target_dev {
+        return Err(io::Error::from_raw_os_error(libc::EXDEV));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ensure_same_device(_source: &Path, _target: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Clone a single file with `FICLONE`, sharing extents with the source.
+#[cfg(target_os = "linux")]
+fn reflink_file(source: &Path, target: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let src = fs::File::open(source)?;
+    let dst = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)?;
+    // SAFETY: both descriptors are valid open files for the duration of the call.
+    let ret = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if ret == -1 {
+        let err = io::Error::last_os_error();
+        drop(dst);
+        let _ = fs::remove_file(target);
+        return Err(err);
+    }
+    dst.set_permissions(src.metadata()?.permissions())
+}
+
+/// Clone a single file with `clonefile(2)`, which also preserves permissions.
+#[cfg(target_os = "macos")]
+fn reflink_file(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(source.as_os_str().as_bytes())?;
+    let dst = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both pointers are valid NUL-terminated strings.
+    let ret = unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink_file(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
 fn remove_entry(path: &Path) -> Result<()> {
     let meta = fs::symlink_metadata(path)?;
     if meta.is_dir() {
@@ -387,9 +1460,19 @@ fn remove_entry(path: &Path) -> Result<()> {
     Ok(())
 }
 
+#[cfg(unix)]
+fn raw_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(not(unix))]
+fn raw_symlink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
 #[cfg(unix)]
 fn symlink(source: &Path, target: &Path) -> Result<()> {
-    std::os::unix::fs::symlink(source, target).with_context(|| {
+    raw_symlink(source, target).with_context(|| {
         format!(
             "Failed to create symlink: {} -> {}",
             target.display(),
@@ -436,4 +1519,645 @@ mod tests {
 
         fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_copy_copies_tree_independently() {
+        let root = unique_temp_dir();
+        let source = root.join("source");
+        fs::create_dir_all(source.join("config")).unwrap();
+        fs::write(source.join("config/database.yml"), "db: main").unwrap();
+        std::os::unix::fs::symlink("database.yml", source.join("config/alias.yml")).unwrap();
+        let target = root.join("target");
+
+        let action = create_copy(
+            &source.join("config"),
+            &target.join("config"),
+            &LinkOptions::default(),
+        )
+        .unwrap();
+        assert!(matches!(action, LinkAction::Copied { .. }));
+
+        let copied = target.join("config/database.yml");
+        assert!(!copied.is_symlink());
+        fs::write(&copied, "db: feature").unwrap();
+        assert_eq!(
+            fs::read_to_string(source.join("config/database.yml")).unwrap(),
+            "db: main"
+        );
+        assert_eq!(
+            fs::read_link(target.join("config/alias.yml")).unwrap(),
+            Path::new("database.yml")
+        );
+
+        // A second run without --force leaves the diverged copy alone.
+        let action = create_copy(
+            &source.join("config"),
+            &target.join("config"),
+            &LinkOptions::default(),
+        )
+        .unwrap();
+        assert!(matches!(action, LinkAction::CopySkipped { .. }));
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "db: feature");
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    /// Apply strategies for real, except that reflinks are unsupported.
+    fn without_reflink(strategy: Strategy, src: &Path, dst: &Path) -> io::Result<()> {
+        match strategy {
+            Strategy::Reflink => Err(io::Error::from(io::ErrorKind::Unsupported)),
+            _ => materialize(strategy, src, dst, &LinkOptions::default()),
+        }
+    }
+
+    #[test]
+    fn create_with_reflink_falls_back_to_copy() {
+        let root = unique_temp_dir();
+        let source = root.join("source");
+        fs::create_dir_all(source.join("target/debug")).unwrap();
+        fs::write(source.join("target/debug/app"), "bin").unwrap();
+        let target = root.join("worktree/target");
+
+        let action = try_strategies(
+            &[Strategy::Reflink, Strategy::Copy],
+            &source.join("target"),
+            &target,
+            &LinkOptions::default(),
+            &without_reflink,
+        )
+        .unwrap();
+        assert_eq!(
+            action,
+            LinkAction::Copied {
+                source: source.join("target"),
+                target: target.clone(),
+            }
+        );
+        assert!(!target.is_symlink());
+        fs::write(target.join("debug/app"), "changed").unwrap();
+        assert_eq!(
+            fs::read_to_string(source.join("target/debug/app")).unwrap(),
+            "bin"
+        );
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn create_with_fails_when_no_strategy_succeeds() {
+        let root = unique_temp_dir();
+        fs::write(root.join("file"), "data").unwrap();
+        let target = root.join("out/file");
+        let opts = LinkOptions::default();
+
+        let err = try_strategies(
+            &[Strategy::Reflink],
+            &root.join("file"),
+            &target,
+            &opts,
+            &without_reflink,
+        )
+        .unwrap_err();
+        let unsupported = io::Error::from(io::ErrorKind::Unsupported);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to create {}: reflink: {unsupported}",
+                target.display()
+            )
+        );
+
+        let err = try_strategies(
+            &[Strategy::Reflink, Strategy::Reflink],
+            &root.join("file"),
+            &target,
+            &opts,
+            &without_reflink,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to create {}: no strategy succeeded (reflink: {unsupported}; reflink: {unsupported})",
+                target.display()
+            )
+        );
+        assert!(!target.exists());
+        assert_eq!(fs::read_dir(root.join("out")).unwrap().count(), 0);
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn create_with_keeps_forced_target_when_no_strategy_succeeds() {
+        let root = unique_temp_dir();
+        fs::write(root.join("file"), "new").unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        let target = root.join("out/file");
+        fs::write(&target, "mine").unwrap();
+        let forced = LinkOptions {
+            force: true,
+            ..Default::default()
+        };
+
+        assert!(try_strategies(
+            &[Strategy::Reflink],
+            &root.join("file"),
+            &target,
+            &forced,
+            &without_reflink,
+        )
+        .is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "mine");
+        assert_eq!(fs::read_dir(root.join("out")).unwrap().count(), 1);
+
+        // Once a strategy succeeds, the new entry replaces it.
+        let action = try_strategies(
+            &[Strategy::Reflink, Strategy::Copy],
+            &root.join("file"),
+            &target,
+            &forced,
+            &without_reflink,
+        )
+        .unwrap();
+        assert!(matches!(action, LinkAction::Copied { .. }), "{action:?}");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(fs::read_dir(root.join("out")).unwrap().count(), 1);
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hardlink_round_trip_with_unlink() {
+        use std::os::unix::fs::MetadataExt;
+
+        let root = unique_temp_dir();
+        let source = root.join("source");
+        let target = root.join("target");
+        fs::create_dir_all(source.join("assets/img")).unwrap();
+        fs::write(source.join("assets/img/logo.png"), "png").unwrap();
+        fs::write(source.join("assets/app.css"), "css").unwrap();
+        fs::create_dir_all(&target).unwrap();
+        // A pre-existing hardlinked file unrelated to the source must survive.
+        fs::write(target.join("own.txt"), "mine").unwrap();
+        fs::hard_link(target.join("own.txt"), root.join("own-alias.txt")).unwrap();
+
+        let action = create_with(
+            &[Strategy::Hardlink],
+            &source.join("assets"),
+            &target.join("assets"),
+            &LinkOptions::default(),
+        )
+        .unwrap();
+        assert!(matches!(action, LinkAction::Hardlinked { .. }));
+        let linked = target.join("assets/img/logo.png");
+        assert!(!linked.is_symlink());
+        assert_eq!(
+            fs::metadata(&linked).unwrap().ino(),
+            fs::metadata(source.join("assets/img/logo.png"))
+                .unwrap()
+                .ino()
+        );
+
+        let actions = unlink_targets(std::slice::from_ref(&source), &[], &target, false).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                UnlinkAction::Removed(target.join("assets/app.css")),
+                UnlinkAction::Removed(target.join("assets/img/logo.png")),
+            ]
+        );
+        assert!(target.join("own.txt").exists());
+        assert!(source.join("assets/img/logo.png").exists());
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn relative_path_finds_shortest_route() {
+        assert_eq!(
+            relative_path(Path::new("/src/wt/feature"), Path::new("/src/main/.env")),
+            Path::new("../../main/.env")
+        );
+        assert_eq!(
+            relative_path(
+                Path::new("/src/feature/config"),
+                Path::new("/src/main/config/master.key")
+            ),
+            Path::new("../../main/config/master.key")
+        );
+        assert_eq!(
+            relative_path(Path::new("/a/b"), Path::new("/a/b/c")),
+            Path::new("c")
+        );
+        assert_eq!(
+            relative_path(Path::new("/a"), Path::new("/a")),
+            Path::new(".")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_links_round_trip_with_unlink() {
+        let root = unique_temp_dir();
+        let root = fs::canonicalize(&root).unwrap();
+        let source = root.join("main");
+        let target = root.join("feature");
+        fs::create_dir_all(source.join("config")).unwrap();
+        fs::write(source.join("config/master.key"), "key").unwrap();
+        fs::write(source.join(".env"), "A=1").unwrap();
+        fs::create_dir_all(&target).unwrap();
+
+        let opts = LinkOptions {
+            relative: true,
+            ..Default::default()
+        };
+        let key_link = target.join("config/master.key");
+        create_link(&source.join("config/master.key"), &key_link, &opts).unwrap();
+        create_link(&source.join(".env"), &target.join(".env"), &opts).unwrap();
+        assert_eq!(
+            fs::read_link(&key_link).unwrap(),
+            Path::new("../../main/config/master.key")
+        );
+        assert_eq!(fs::read_to_string(&key_link).unwrap(), "key");
+
+        // Dangling relative links into the source are still recognized.
+        fs::remove_file(source.join(".env")).unwrap();
+
+        let actions = unlink_targets(std::slice::from_ref(&source), &[], &target, false).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                UnlinkAction::Removed(target.join(".env")),
+                UnlinkAction::Removed(key_link.clone()),
+            ]
+        );
+        assert!(!key_link.is_symlink());
+        assert!(source.join("config/master.key").exists());
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unlink_targets_recognizes_every_source() {
+        let root = fs::canonicalize(unique_temp_dir()).unwrap();
+        let source = root.join("main");
+        let certs = root.join("certs");
+        let other = root.join("other");
+        let target = root.join("feature");
+        for dir in [&source, &certs, &other, &target] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(source.join(".env"), "").unwrap();
+        fs::write(certs.join("dev.pem"), "").unwrap();
+        fs::write(other.join("x"), "").unwrap();
+
+        let opts = LinkOptions::default();
+        create_link(&source.join(".env"), &target.join(".env"), &opts).unwrap();
+        create_link(&certs.join("dev.pem"), &target.join("dev.pem"), &opts).unwrap();
+        create_link(&other.join("x"), &target.join("x"), &opts).unwrap();
+
+        let actions = unlink_targets(&[source, certs], &[], &target, true).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                UnlinkAction::Removed(target.join(".env")),
+                UnlinkAction::Removed(target.join("dev.pem")),
+            ]
+        );
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unlink_targets_recognizes_hardlinks_at_mapped_destinations() {
+        let root = unique_temp_dir();
+        let source = root.join("main");
+        let target = root.join("feature");
+        fs::create_dir_all(source.join("shared/fixtures")).unwrap();
+        fs::create_dir_all(target.join("test/fixtures")).unwrap();
+        fs::write(source.join("shared/app.json"), "").unwrap();
+        fs::write(source.join("shared/fixtures/a.json"), "").unwrap();
+        fs::hard_link(source.join("shared/app.json"), target.join("app.json")).unwrap();
+        fs::hard_link(
+            source.join("shared/fixtures/a.json"),
+            target.join("test/fixtures/a.json"),
+        )
+        .unwrap();
+
+        let sources = std::slice::from_ref(&source);
+        assert_eq!(unlink_targets(sources, &[], &target, true).unwrap(), vec![]);
+
+        let mapped = [
+            (PathBuf::from("app.json"), source.join("shared/app.json")),
+            (
+                PathBuf::from("test/fixtures"),
+                source.join("shared/fixtures"),
+            ),
+        ];
+        assert_eq!(
+            unlink_targets(sources, &mapped, &target, true).unwrap(),
+            vec![
+                UnlinkAction::Removed(target.join("app.json")),
+                UnlinkAction::Removed(target.join("test/fixtures/a.json")),
+            ]
+        );
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn create_with_template_renders_vars() {
+        let root = unique_temp_dir();
+        fs::write(
+            root.join(".env"),
+            "COMPOSE_PROJECT_NAME=app-{{ branch_slug }}\nDEBUG=1\n",
+        )
+        .unwrap();
+        let target = root.join("feature/.env");
+        let mut opts = LinkOptions::default();
+        opts.vars.insert("branch_slug", "feature-login");
+
+        let action =
+            create_with(&[Strategy::Template], &root.join(".env"), &target, &opts).unwrap();
+        assert!(matches!(action, LinkAction::Rendered { .. }));
+        assert_eq!(
+            fs::read_to_string(&target).unwrap(),
+            "COMPOSE_PROJECT_NAME=app-feature-login\nDEBUG=1\n"
+        );
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_dir_mirrors_permissions_and_unlinks_only_when_empty() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = unique_temp_dir();
+        let source = root.join("main/tmp");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("server.pid"), "123").unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o700)).unwrap();
+        let target = root.join("feature/tmp");
+        let opts = LinkOptions::default();
+
+        let action =
+            create_with(&[Strategy::Mkdir { keep: true }], &source, &target, &opts).unwrap();
+        assert!(matches!(action, LinkAction::DirCreated { .. }));
+        assert!(!target.is_symlink());
+        assert!(!target.join("server.pid").exists());
+        assert!(target.join(".keep").is_file());
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // Existing real directories are never replaced, even with --force.
+        let forced = LinkOptions {
+            force: true,
+            ..Default::default()
+        };
+        let action = create_dir(&source, &target, true, &forced).unwrap();
+        assert!(matches!(action, LinkAction::Skipped { .. }));
+
+        fs::write(target.join("server.pid"), "456").unwrap();
+        assert!(matches!(
+            remove_empty_dir(&target, false).unwrap(),
+            Some(UnlinkAction::Skipped { .. })
+        ));
+        assert!(target.is_dir());
+
+        fs::remove_file(target.join("server.pid")).unwrap();
+        assert_eq!(
+            remove_empty_dir(&target, false).unwrap(),
+            Some(UnlinkAction::Removed(target.clone()))
+        );
+        assert!(!target.exists());
+        assert_eq!(remove_empty_dir(&target, false).unwrap(), None);
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn adopt_moves_into_source_and_links_back() {
+        let root = unique_temp_dir();
+        let source = root.join("main");
+        let target = root.join("feature");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(target.join("node_modules/pkg")).unwrap();
+        fs::write(target.join("node_modules/pkg/index.js"), "js").unwrap();
+        fs::write(target.join(".env.local"), "new").unwrap();
+        fs::write(source.join(".env.local"), "old").unwrap();
+        let opts = LinkOptions::default();
+
+        let action = adopt(&source, &target, Path::new("node_modules"), &opts).unwrap();
+        assert!(matches!(action, LinkAction::Adopted { .. }));
+        assert_eq!(
+            fs::read_link(target.join("node_modules")).unwrap(),
+            source.join("node_modules")
+        );
+        assert_eq!(
+            fs::read_to_string(source.join("node_modules/pkg/index.js")).unwrap(),
+            "js"
+        );
+
+        // Already linked now, so a second adopt is a no-op.
+        let action = adopt(&source, &target, Path::new("node_modules"), &opts).unwrap();
+        assert!(matches!(action, LinkAction::Skipped { .. }));
+
+        // The source copy wins unless --force is given.
+        let action = adopt(&source, &target, Path::new(".env.local"), &opts).unwrap();
+        assert!(matches!(action, LinkAction::Skipped { .. }));
+        assert!(!target.join(".env.local").is_symlink());
+
+        let forced = LinkOptions {
+            force: true,
+            ..Default::default()
+        };
+        adopt(&source, &target, Path::new(".env.local"), &forced).unwrap();
+        assert!(target.join(".env.local").is_symlink());
+        assert_eq!(
+            fs::read_to_string(source.join(".env.local")).unwrap(),
+            "new"
+        );
+        assert!(!sibling(&source.join(".env.local"), "old").exists());
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn adopt_refuses_the_root_and_git_dir() {
+        let root = unique_temp_dir();
+        let source = root.join("main");
+        let target = root.join("feature");
+        fs::create_dir_all(source.join(".git")).unwrap();
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join(".git"), "gitdir: ../main/.git").unwrap();
+        let forced = LinkOptions {
+            force: true,
+            ..Default::default()
+        };
+
+        for rel in [
+            "",
+            ".",
+            ".git",
+            ".git/config",
+            "sub/..",
+            "sub/../",
+            "../main",
+        ] {
+            assert!(
+                adopt(&source, &target, Path::new(rel), &forced).is_err(),
+                "adopted `{rel}`"
+            );
+        }
+        assert!(source.join(".git").is_dir());
+        assert!(target.join("sub").is_dir());
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn swap_in_restores_the_link_on_failure() {
+        let root = unique_temp_dir();
+        let source = root.join("main");
+        fs::create_dir_all(&source).unwrap();
+        // A directory can't be moved into itself, so the copy can't take the
+        // place of a link inside it.
+        let tmp = root.join("copy");
+        fs::create_dir_all(&tmp).unwrap();
+        let link = tmp.join("link");
+        std::os::unix::fs::symlink(&source, &link).unwrap();
+
+        assert!(swap_in(&tmp, &link).is_err());
+        assert_eq!(fs::read_link(&link).unwrap(), source);
+        assert_eq!(fs::read_dir(&tmp).unwrap().count(), 1);
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn materialize_targets_replaces_selected_links_with_copies() {
+        let root = unique_temp_dir();
+        let source = root.join("main");
+        let target = root.join("feature");
+        fs::create_dir_all(source.join("node_modules/pkg")).unwrap();
+        fs::write(source.join("node_modules/pkg/index.js"), "js").unwrap();
+        fs::write(source.join(".env"), "A=1").unwrap();
+        fs::create_dir_all(&target).unwrap();
+        let opts = LinkOptions::default();
+        create_link(
+            &source.join("node_modules"),
+            &target.join("node_modules"),
+            &opts,
+        )
+        .unwrap();
+        create_link(&source.join(".env"), &target.join(".env"), &opts).unwrap();
+
+        // Dry run and path selection.
+        let actions = materialize_targets(
+            std::slice::from_ref(&source),
+            &[],
+            &target,
+            &[target.join(".env")],
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            actions,
+            vec![UnlinkAction::Materialized(target.join(".env"))]
+        );
+        assert!(target.join(".env").is_symlink());
+
+        let actions =
+            materialize_targets(std::slice::from_ref(&source), &[], &target, &[], false).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                UnlinkAction::Materialized(target.join(".env")),
+                UnlinkAction::Materialized(target.join("node_modules")),
+            ]
+        );
+        assert!(!target.join(".env").is_symlink());
+        assert!(!target.join("node_modules").is_symlink());
+        fs::write(target.join("node_modules/pkg/index.js"), "changed").unwrap();
+        assert_eq!(
+            fs::read_to_string(source.join("node_modules/pkg/index.js")).unwrap(),
+            "js"
+        );
+        // Nothing left to detach or unlink.
+        assert!(
+            unlink_targets(std::slice::from_ref(&source), &[], &target, false)
+                .unwrap()
+                .is_empty()
+        );
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn create_copy_dry_run_makes_no_changes() {
+        let root = unique_temp_dir();
+        fs::write(root.join(".env.local"), "A=1").unwrap();
+        let target = root.join("target/.env.local");
+
+        let action = create_copy(
+            &root.join(".env.local"),
+            &target,
+            &LinkOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(matches!(action, LinkAction::Copied { .. }));
+        assert!(!target.exists());
+
+        fs::remove_dir_all(&root).expect("failed to cleanup temp dir");
+    }
+
+    #[test]
+    fn backup_existing_moves_conflicts_aside() {
+        let base = unique_temp_dir();
+        let source = base.join("src");
+        let target = base.join("dst");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(source.join(".env"), "shared").unwrap();
+        fs::write(target.join(".env"), "mine").unwrap();
+        fs::write(target.join(".env.bak"), "older").unwrap();
+
+        let opts = LinkOptions::default();
+        assert_eq!(
+            backup_existing(&source.join(".env"), &target.join(".env"), false).unwrap(),
+            Some(LinkAction::BackedUp {
+                target: target.join(".env"),
+                backup: target.join(".env.bak.1"),
+            })
+        );
+        assert_eq!(
+            fs::read_to_string(target.join(".env.bak.1")).unwrap(),
+            "mine"
+        );
+
+        create_link(&source.join(".env"), &target.join(".env"), &opts).unwrap();
+        // An existing link to the source is not a conflict.
+        assert_eq!(
+            backup_existing(&source.join(".env"), &target.join(".env"), false).unwrap(),
+            None
+        );
+        assert_eq!(
+            backup_existing(&source.join("x"), &target.join("missing"), false).unwrap(),
+            None
+        );
+
+        let _ = fs::remove_dir_all(&base);
+    }
 }
@@ -1,19 +1,37 @@
+mod check;
 mod cli;
 mod config;
 mod git;
+mod init;
 mod linker;
+mod registry;
+mod template;
+mod user_config;
 mod walker;
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
 use colored::Colorize;
+use std::collections::BTreeMap;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-use cli::Cli;
+use cli::{Cli, Command};
 use config::Config;
+use user_config::UserConfig;
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    let user = if cli.no_user_config {
+        UserConfig::default()
+    } else {
+        UserConfig::load()?
+    };
+    // `init` only overwrites, and only writes elsewhere, when told to on the
+    // command line, never because of a user config default.
+    let init_path = cli.config.first().cloned();
+    let init_force = cli.force;
+    user.apply_to(&mut cli);
 
     // Set up tracing
     let level = if cli.verbose { "debug" } else { "warn" };
@@ -26,15 +44,16 @@ fn main() -> Result<()> {
         .init();
 
     // Resolve target directory
-    let target = fs::canonicalize(&cli.target)
-        .with_context(|| format!("Target directory does not exist: {}", cli.target.display()))?;
+    let cli_target = cli.target.clone().unwrap_or_else(|| PathBuf::from("."));
+    let target = fs::canonicalize(&cli_target)
+        .with_context(|| format!("Target directory does not exist: {}", cli_target.display()))?;
 
     if !target.is_dir() {
         bail!("Target is not a directory: {}", target.display());
     }
 
     // Resolve source directory
-    let source = match cli.source {
+    let source = match cli.source.clone() {
         Some(s) => {
             let resolved = fs::canonicalize(&s)
                 .with_context(|| format!("Source directory does not exist: {}", s.display()))?;
@@ -46,6 +65,45 @@ fn main() -> Result<()> {
         None => git::detect_main_worktree_in(&target)?,
     };
 
+    if let Some(Command::Env) = cli.command {
+        return run_env(&source, &target);
+    }
+
+    let config_paths = if cli.config.is_empty() {
+        vec![config::default_path(&source)]
+    } else {
+        cli.config.clone()
+    };
+    if config_paths
+        .iter()
+        .filter(|p| p.as_path() == Path::new(config::STDIN))
+        .count()
+        > 1
+    {
+        bail!("The config can only be read from stdin once");
+    }
+    // Messages and `adopt --add` refer to the first config file.
+    let config_path = config_paths[0].clone();
+    let config_layers = config::layers(&config_paths, !cli.no_user_config);
+    let groups = config::GroupSelection {
+        profile: cli.profile.clone(),
+        only: cli.only.clone(),
+        skip: cli.skip.clone(),
+    };
+    let extra = config::ExtraPatterns {
+        patterns: cli.patterns.clone(),
+        excludes: cli.excludes.clone(),
+        replace: cli.only_cli_patterns,
+    };
+
+    if let Some(Command::Init) = cli.command {
+        return run_init(&source, init_path.as_deref(), init_force, cli.dry_run);
+    }
+
+    if let Some(Command::Check) = cli.command {
+        return run_check(&source, &target, &config_layers, &user, &groups, &extra);
+    }
+
     if source == target {
         bail!("Source and target cannot be the same directory");
     }
@@ -58,24 +116,110 @@ fn main() -> Result<()> {
         println!("{}", "DRY RUN — no changes will be made".cyan().bold());
     }
 
-    if cli.unlink {
-        // Unlink mode: walk the target directory looking for symlinks into source.
-        // No config file needed — we scan target for any symlink pointing into source.
-        let actions = linker::unlink_targets(&source, &target, cli.dry_run)?;
+    if let Some(Command::Adopt { paths, add }) = &cli.command {
+        let config = load_config(&source, &config_layers, &user, &target, &groups, &extra)?
+            .unwrap_or_default();
+        let opts = linker::LinkOptions {
+            force: cli.force || (config.force && !cli.no_force),
+            dry_run: cli.dry_run,
+            relative: cli.relative || (config.relative && !cli.no_relative),
+            ..Default::default()
+        };
+        return run_adopt(&source, &target, paths, *add, &config_path, &opts);
+    }
+
+    if cli.unlink || matches!(cli.command, Some(Command::Detach { .. })) {
+        // Walk the target directory looking for symlinks into source (and
+        // hardlinks of source files). The config is only consulted for
+        // `@source` roots, mapped destinations and `mkdir:` entries, so a
+        // missing or broken config must not prevent cleaning up.
+        let files = load_files(&source, &config_layers).unwrap_or_else(|e| {
+            tracing::warn!("Could not load config, only unlinking links into source: {e:#}");
+            None
+        });
+        let sources = files
+            .as_ref()
+            .map(|config| unlink_sources(config, &target))
+            .unwrap_or_default();
+        let mut roots = vec![source.clone()];
+        roots.extend(sources.values().cloned());
+
+        // Hardlinks at mapped destinations and directories from `mkdir:`
+        // entries can't be recognized from the target alone, so they are
+        // found through the config.
+        let is_mkdir = |entry: &config::Entry| {
+            entry
+                .strategies
+                .iter()
+                .any(|s| matches!(s, linker::Strategy::Mkdir { .. }))
+        };
+        let config = finish_config(files, &user, &target, &groups, &extra)
+            .inspect_err(|e| tracing::warn!("Could not load config: {e:#}"))
+            .ok()
+            .flatten()
+            .filter(|config| {
+                config
+                    .entries
+                    .iter()
+                    .any(|e| e.destination.is_some() || is_mkdir(e))
+            });
+        let placed = config.as_ref().map(|config| -> Result<Vec<_>> {
+            let targets =
+                walker::collect_from_sources(&source, &sources, &config.entries, cli.no_ignore)?;
+            let paths = walker::destinations(&targets, &config.entries)?;
+            Ok(targets.into_iter().zip(paths).collect())
+        });
+        let placed = placed
+            .transpose()
+            .inspect_err(|e| tracing::warn!("Could not match the config: {e:#}"))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let entries = config.as_ref().map_or(&[][..], |config| &config.entries);
+        let mapped: Vec<(PathBuf, PathBuf)> = placed
+            .iter()
+            .filter(|(t, _)| entries[t.entry].destination.is_some())
+            .map(|(t, rel)| (rel.clone(), t.path.clone()))
+            .collect();
+
+        if let Some(Command::Detach { paths }) = &cli.command {
+            let only = paths
+                .iter()
+                .map(|p| resolve_in_target(p, &target))
+                .collect::<Result<Vec<_>>>()?;
+            return run_detach(&roots, &mapped, &target, &only, cli.dry_run);
+        }
+        if cli.materialize {
+            return run_detach(&roots, &mapped, &target, &[], cli.dry_run);
+        }
+
+        let mut actions = linker::unlink_targets(&roots, &mapped, &target, cli.dry_run)?;
+
+        // `mkdir:` directories are removed only while still empty.
+        for (t, rel) in &placed {
+            if !is_mkdir(&entries[t.entry]) {
+                continue;
+            }
+            if let Some(action) = linker::remove_empty_dir(&target.join(rel), cli.dry_run)? {
+                actions.push(action);
+            }
+        }
 
         let mut removed = 0;
         let mut skipped = 0;
         for action in &actions {
             println!("  {action}");
             match action {
-                linker::UnlinkAction::Removed(_) => removed += 1,
+                linker::UnlinkAction::Removed(_) | linker::UnlinkAction::Materialized(_) => {
+                    removed += 1
+                }
                 linker::UnlinkAction::Skipped { .. } => skipped += 1,
             }
         }
 
         if actions.is_empty() {
             println!(
-                "  {} No symlinks pointing to source found",
+                "  {} No links pointing to source found",
                 "INFO".cyan().bold()
             );
         }
@@ -87,13 +231,15 @@ fn main() -> Result<()> {
         );
     } else {
         // Link mode: read config and collect matching files/directories from source
-        let config_path = cli
-            .config
-            .clone()
-            .unwrap_or_else(|| source.join(".worktreelinks"));
-        let config = Config::from_file(&config_path)?;
+        let Some(config) = load_config(&source, &config_layers, &user, &target, &groups, &extra)?
+        else {
+            bail!(
+                "Config file not found: {} (and no worktree-link.pattern in git config)",
+                config_path.display()
+            );
+        };
 
-        if config.patterns.is_empty() {
+        if config.entries.is_empty() {
             println!(
                 "{} No patterns found in {}",
                 "WARN".yellow().bold(),
@@ -102,7 +248,10 @@ fn main() -> Result<()> {
             return Ok(());
         }
 
-        let targets = walker::collect_targets(&source, &config.patterns, cli.no_ignore)?;
+        let sources = external_sources(&config, &target)?;
+        let targets =
+            walker::collect_from_sources(&source, &sources, &config.entries, cli.no_ignore)?;
+        let paths = walker::destinations(&targets, &config.entries)?;
 
         if targets.is_empty() {
             println!(
@@ -117,32 +266,495 @@ fn main() -> Result<()> {
             println!("Found {} target(s) to link", targets.len());
         }
 
+        // Template variables need the target's branch; only ask git when used.
+        let uses_template = config
+            .entries
+            .iter()
+            .any(|e| e.strategies.contains(&linker::Strategy::Template));
+        let vars = if uses_template {
+            worktree_vars(&source, &target, !cli.dry_run)?
+        } else {
+            template::Vars::default()
+        };
+
+        let force = cli.force || (config.force && !cli.no_force);
+        let mut opts = linker::LinkOptions {
+            force,
+            dry_run: cli.dry_run,
+            relative: cli.relative || (config.relative && !cli.no_relative),
+            vars,
+        };
+
         let mut created = 0;
+        let mut copied = 0;
+        let mut reflinked = 0;
+        let mut hardlinked = 0;
+        let mut rendered = 0;
+        let mut dirs = 0;
         let mut overwritten = 0;
+        let mut backed_up = 0;
         let mut skipped = 0;
 
-        for source_path in &targets {
-            let rel = source_path
-                .strip_prefix(&source)
-                .with_context(|| "Path is not relative to source")?;
+        for (
+            walker::Target {
+                path: source_path,
+                entry,
+                ..
+            },
+            rel,
+        ) in targets.iter().zip(&paths)
+        {
             let target_path = target.join(rel);
+            let entry = &config.entries[*entry];
 
-            let action = linker::create_link(source_path, &target_path, cli.force, cli.dry_run)?;
+            // --force overrides every entry's own conflict policy.
+            let on_conflict = if force {
+                linker::OnConflict::Overwrite
+            } else {
+                entry.on_conflict
+            };
+            opts.force = on_conflict == linker::OnConflict::Overwrite;
+            if on_conflict == linker::OnConflict::Backup {
+                if let Some(backup) =
+                    linker::backup_existing(source_path, &target_path, cli.dry_run)?
+                {
+                    println!("  {backup}");
+                    backed_up += 1;
+                    // In a dry run the entry is still there; report it as replaced.
+                    opts.force = true;
+                }
+            }
+
+            let action = linker::create_with(&entry.strategies, source_path, &target_path, &opts)?;
 
             println!("  {action}");
             match action {
-                linker::LinkAction::Created { .. } => created += 1,
+                linker::LinkAction::Created { .. } | linker::LinkAction::Adopted { .. } => {
+                    created += 1
+                }
                 linker::LinkAction::Overwritten { .. } => overwritten += 1,
-                linker::LinkAction::Skipped { .. } => skipped += 1,
+                linker::LinkAction::Skipped { .. } | linker::LinkAction::CopySkipped { .. } => {
+                    skipped += 1
+                }
+                linker::LinkAction::Copied { .. } => copied += 1,
+                linker::LinkAction::Reflinked { .. } => reflinked += 1,
+                linker::LinkAction::Hardlinked { .. } => hardlinked += 1,
+                linker::LinkAction::Rendered { .. } => rendered += 1,
+                linker::LinkAction::DirCreated { .. } => dirs += 1,
+                linker::LinkAction::BackedUp { .. } => backed_up += 1,
+            }
+        }
+
+        // Non-symlink strategies are only reported when they were used.
+        let mut summary = vec![format!("Created: {created}")];
+        for (label, count) in [
+            ("Copied", copied),
+            ("Reflinked", reflinked),
+            ("Hardlinked", hardlinked),
+            ("Rendered", rendered),
+            ("Directories", dirs),
+            ("Backed up", backed_up),
+        ] {
+            if count > 0 {
+                summary.push(format!("{label}: {count}"));
             }
         }
+        summary.push(format!("Overwritten: {overwritten}"));
+        summary.push(format!("Skipped: {skipped}"));
 
         println!();
+        println!("{}", summary.join(", ").bold());
+    }
+
+    Ok(())
+}
+
+/// Write a starter config for `source` to `path`, or `<SOURCE>/.worktreelinks`.
+fn run_init(source: &Path, path: Option<&Path>, force: bool, dry_run: bool) -> Result<()> {
+    let config_path = path.map_or_else(|| source.join(".worktreelinks"), Path::to_path_buf);
+    if config_path == Path::new(config::STDIN) {
+        bail!("Cannot write the config to stdin");
+    }
+    if config_path.extension().is_some_and(|ext| ext == "toml") {
+        bail!(
+            "init writes the line format, not TOML: {}",
+            config_path.display()
+        );
+    }
+    // Without -c, a TOML config would take precedence over what we write.
+    let toml = source.join(".worktreelinks.toml");
+    if path.is_none() && toml.exists() {
+        bail!(
+            "Config file already exists: {} (init can't replace a TOML config; remove it or use -c)",
+            toml.display()
+        );
+    }
+    if config_path.exists() && !force {
+        bail!(
+            "Config file already exists: {} (use --force to overwrite)",
+            config_path.display()
+        );
+    }
+
+    let found = init::scan(source)?;
+    let content = init::render(&found);
+    if dry_run {
+        print!("{content}");
+        return Ok(());
+    }
+    fs::write(&config_path, &content)
+        .with_context(|| format!("Failed to write config file: {}", config_path.display()))?;
+    println!(
+        "{} Wrote {} with {} ignored entr{}",
+        "INFO".cyan().bold(),
+        config_path.display(),
+        found.len(),
+        if found.len() == 1 { "y" } else { "ies" }
+    );
+    Ok(())
+}
+
+/// Lint the effective config and fail if anything was found.
+fn run_check(
+    source: &Path,
+    target: &Path,
+    layers: &[PathBuf],
+    user: &UserConfig,
+    groups: &config::GroupSelection,
+    extra: &config::ExtraPatterns,
+) -> Result<()> {
+    let files = load_files(source, layers)?;
+    let Some(config) = base_config(files, user, target, extra) else {
+        bail!("No config found to check");
+    };
+    // Every entry is linted, but only the selected ones (in the selected
+    // groups and matching `[branch]` sections) need to match something.
+    let selected = complete_config(config.clone(), user, target, Some(groups), extra)?;
+    let config = complete_config(config, user, target, None, extra)?;
+    let mut rest = selected.entries.iter().peekable();
+    let mask: Vec<bool> = config
+        .entries
+        .iter()
+        .map(|entry| rest.next_if(|s| *s == entry).is_some())
+        .collect();
+
+    let findings = check::check(source, &config.entries, &mask)?;
+    for finding in &findings {
+        println!("  {}", finding.display(&config.entries, source));
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == check::Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+    println!();
+    println!(
+        "{}",
+        format!(
+            "Checked {} pattern(s): {errors} error(s), {warnings} warning(s)",
+            config.entries.len()
+        )
+        .bold()
+    );
+
+    if !findings.is_empty() {
+        bail!("Config check found {} problem(s)", findings.len());
+    }
+    Ok(())
+}
+
+/// Load the effective config: the config file layers, then `worktree-link.*`
+/// values from git config as seen from `target`. Config files in
+/// subdirectories of `source` come right after the layers. Without any, the user
+/// config's fallback patterns are used. Only entries in the selected `groups`
+/// are kept, followed by the command-line patterns in `extra`. Returns `None`
+/// if there is nothing to load at all.
+fn load_config(
+    source: &Path,
+    layers: &[PathBuf],
+    user: &UserConfig,
+    target: &Path,
+    groups: &config::GroupSelection,
+    extra: &config::ExtraPatterns,
+) -> Result<Option<Config>> {
+    finish_config(load_files(source, layers)?, user, target, groups, extra)
+}
+
+/// Read the config file layers and, if they opt in with `%nested`, the config
+/// files in subdirectories of `source`. Returns `None` if there are none.
+fn load_files(source: &Path, layers: &[PathBuf]) -> Result<Option<Config>> {
+    if !layers.iter().any(|p| config::exists(p)) {
+        return Ok(None);
+    }
+    let mut config = Config::from_layers(layers)?;
+    if config.nested {
+        let nested = walker::nested_configs(source)?;
+        config.merge_nested(source, &nested)?;
+    }
+    Ok(Some(config))
+}
+
+/// The rest of [`load_config`] on top of the config `files`.
+fn finish_config(
+    files: Option<Config>,
+    user: &UserConfig,
+    target: &Path,
+    groups: &config::GroupSelection,
+    extra: &config::ExtraPatterns,
+) -> Result<Option<Config>> {
+    let Some(config) = base_config(files, user, target, extra) else {
+        return Ok(None);
+    };
+    complete_config(config, user, target, Some(groups), extra).map(Some)
+}
+
+/// Add git config to the config `files`, or fall back to the user config's
+/// patterns. Returns `None` if there is nothing to load at all.
+fn base_config(
+    files: Option<Config>,
+    user: &UserConfig,
+    target: &Path,
+    extra: &config::ExtraPatterns,
+) -> Option<Config> {
+    let has_files = files.is_some();
+    let mut config = files.unwrap_or_default();
+    if let Err(e) = config.merge_git_config(target) {
+        tracing::warn!("Could not read git config: {e:#}");
+    }
+
+    if !has_files && config.entries.is_empty() && !extra.replace {
+        if !user.patterns.is_empty() {
+            println!(
+                "{} No config file found, using patterns from the user config",
+                "INFO".cyan().bold()
+            );
+            config.entries = Config::from_patterns(&user.patterns).entries;
+        } else if extra.is_empty() {
+            return None;
+        }
+    }
+    Some(config)
+}
+
+/// Keep the entries in the selected `groups` and of the `[branch]` sections
+/// matching the target (every entry if `groups` is `None`), then add the
+/// command-line patterns and the default strategies.
+fn complete_config(
+    mut config: Config,
+    user: &UserConfig,
+    target: &Path,
+    groups: Option<&config::GroupSelection>,
+    extra: &config::ExtraPatterns,
+) -> Result<Config> {
+    if let Some(groups) = groups {
+        config.select_groups(groups)?;
+        if config.entries.iter().any(|e| e.branch.is_some()) {
+            let branch = git::branch_in(target).unwrap_or_else(|e| {
+                tracing::warn!("Could not determine the target branch: {e:#}");
+                None
+            });
+            config.select_branch(branch.as_deref())?;
+        }
+    }
+    config.add_extra(extra);
+    if let Some(strategies) = user.strategies()? {
+        config.set_default_strategies(&strategies);
+    }
+    Ok(config)
+}
+
+/// Resolve the `@source` declarations of `config`. Sources that don't exist
+/// are skipped with a warning, so their entries only use the main worktree.
+fn external_sources(config: &Config, target: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let mut sources = BTreeMap::new();
+    for (name, path) in &config.sources {
+        let resolved = match fs::canonicalize(path) {
+            Ok(resolved) if resolved.is_dir() => resolved,
+            _ => {
+                tracing::warn!("Source `{name}` is not a directory: {}", path.display());
+                continue;
+            }
+        };
+        if target.starts_with(&resolved) || resolved.starts_with(target) {
+            bail!(
+                "Source `{name}` and target must not be nested: {}",
+                resolved.display()
+            );
+        }
+        sources.insert(name.clone(), resolved);
+    }
+    Ok(sources)
+}
+
+/// Resolve the `@source` declarations of `config` for unlinking. Unlike
+/// [`external_sources`], a source nested with the target is only skipped with
+/// a warning, so that links into the other roots can still be removed.
+fn unlink_sources(config: &Config, target: &Path) -> BTreeMap<String, PathBuf> {
+    let mut sources = BTreeMap::new();
+    for (name, path) in &config.sources {
+        match fs::canonicalize(path) {
+            Ok(resolved) if resolved.is_dir() => {
+                if target.starts_with(&resolved) || resolved.starts_with(target) {
+                    tracing::warn!(
+                        "Source `{name}` and target are nested, skipping: {}",
+                        resolved.display()
+                    );
+                } else {
+                    sources.insert(name.clone(), resolved);
+                }
+            }
+            _ => tracing::warn!("Source `{name}` is not a directory: {}", path.display()),
+        }
+    }
+    sources
+}
+
+/// Move each path from the target worktree into the source and link it back.
+fn run_adopt(
+    source: &Path,
+    target: &Path,
+    paths: &[PathBuf],
+    add: bool,
+    config_path: &Path,
+    opts: &linker::LinkOptions,
+) -> Result<()> {
+    let mut adopted = 0;
+    let mut skipped = 0;
+
+    if add && config_path == Path::new(config::STDIN) {
+        bail!("Cannot add patterns to a config read from stdin");
+    }
+
+    for path in paths {
+        let target_path = resolve_in_target(path, target)?;
+        let rel = target_path
+            .strip_prefix(target)
+            .with_context(|| "Path is not relative to target")?;
+
+        let action = linker::adopt(source, target, rel, opts)?;
+        println!("  {action}");
+        match action {
+            linker::LinkAction::Adopted { .. } => adopted += 1,
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        }
+
+        if add {
+            let pattern = format!("/{}", rel.display());
+            if opts.dry_run {
+                println!("  would add `{pattern}` to {}", config_path.display());
+            } else if config::append_pattern(config_path, &pattern)? {
+                println!("  added `{pattern}` to {}", config_path.display());
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "{}",
+        format!("Adopted: {adopted}, Skipped: {skipped}").bold()
+    );
+    Ok(())
+}
+
+/// Replace links into the sources with copies, optionally limited to `only`.
+fn run_detach(
+    sources: &[PathBuf],
+    mapped: &[(PathBuf, PathBuf)],
+    target: &Path,
+    only: &[PathBuf],
+    dry_run: bool,
+) -> Result<()> {
+    let actions = linker::materialize_targets(sources, mapped, target, only, dry_run)?;
+
+    let mut detached = 0;
+    let mut skipped = 0;
+    for action in &actions {
+        println!("  {action}");
+        match action {
+            linker::UnlinkAction::Materialized(_) => detached += 1,
+            linker::UnlinkAction::Removed(_) => {}
+            linker::UnlinkAction::Skipped { .. } => skipped += 1,
+        }
+    }
+
+    if actions.is_empty() {
         println!(
-            "{}",
-            format!("Created: {created}, Overwritten: {overwritten}, Skipped: {skipped}").bold()
+            "  {} No links pointing to source found",
+            "INFO".cyan().bold()
         );
     }
 
+    println!();
+    println!(
+        "{}",
+        format!("Detached: {detached}, Skipped: {skipped}").bold()
+    );
+    Ok(())
+}
+
+/// Resolve a user-supplied path to an absolute path inside `target`.
+///
+/// Only the parent is canonicalized, since the entry itself may be a
+/// symlink that must not be followed.
+fn resolve_in_target(path: &Path, target: &Path) -> Result<PathBuf> {
+    let absolute = std::path::absolute(path)
+        .with_context(|| format!("Failed to resolve path: {}", path.display()))?;
+    let resolved = match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .with_context(|| format!("Directory does not exist: {}", parent.display()))?
+            .join(name),
+        // `.`/`..` or the filesystem root: nothing to protect, resolve fully.
+        _ => fs::canonicalize(&absolute)
+            .with_context(|| format!("Path does not exist: {}", path.display()))?,
+    };
+    if !resolved.starts_with(target) {
+        bail!("Path is not inside the target: {}", path.display());
+    }
+    Ok(resolved)
+}
+
+/// Print the target worktree's variables as shell `export` lines.
+fn run_env(source: &Path, target: &Path) -> Result<()> {
+    let vars = worktree_vars(source, target, true)?;
+    for (name, value) in vars.iter() {
+        println!(
+            "export WTL_{}={}",
+            name.to_ascii_uppercase(),
+            shell_quote(value)
+        );
+    }
     Ok(())
 }
+
+/// Collect template variables for `target`, including its slot from the
+/// registry in the git common directory. `persist` controls whether a newly
+/// allocated slot is saved (false for dry runs).
+///
+/// Outside a git repository there are no worktrees to tell apart, so the
+/// branch is empty and the slot is `0`.
+fn worktree_vars(source: &Path, target: &Path, persist: bool) -> Result<template::Vars> {
+    let worktrees = git::list_worktrees_in(target)
+        .inspect_err(|e| tracing::warn!("Could not list worktrees: {e:#}"))
+        .ok();
+    let mut vars =
+        template::Vars::for_worktree(source, target, worktrees.as_deref().unwrap_or_default());
+
+    let slot = match git::common_dir_in(target) {
+        Ok(common_dir) => registry::assign(&common_dir, target, worktrees.as_deref(), persist)?,
+        Err(e) => {
+            tracing::warn!("Could not locate git common directory, using slot 0: {e:#}");
+            0
+        }
+    };
+    vars.insert_slot(slot);
+    Ok(vars)
+}
+
+/// Quote `value` for POSIX shells using single quotes.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,325 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::git::Worktree;
+
+/// Ports reserved per slot: slot `n` gets offset `n * PORT_STRIDE`.
+pub const PORT_STRIDE: u32 = 10;
+
+/// Location of the registry inside the git common directory, so it is
+/// shared by every worktree of the repository.
+const REGISTRY_FILE: &str = "worktree-link/slots";
+
+/// Locked exclusively while a run reads, updates and saves the registry, so
+/// concurrent runs can't hand out the same slot. The OS drops the lock when
+/// its holder exits, so a crashed run never leaves it stuck.
+const LOCK_FILE: &str = "worktree-link/slots.lock";
+
+/// How long to wait for another run to release the lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stable per-worktree slot numbers, persisted in the git common directory.
+///
+/// The main worktree always has slot `0` and is never stored. Other
+/// worktrees get the lowest free slot starting at `1`, which they keep until
+/// they disappear from `git worktree list`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Registry {
+    slots: BTreeMap<PathBuf, u32>,
+}
+
+impl Registry {
+    /// Parse the registry file. Each line is `<slot>\t<worktree path>`;
+    /// blank lines, `#` comments and malformed lines are ignored.
+    fn parse(content: &str) -> Self {
+        let slots = content
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (slot, path) = line.split_once('\t')?;
+                Some((PathBuf::from(path), slot.trim().parse().ok()?))
+            })
+            .collect();
+        Registry { slots }
+    }
+
+    fn serialize(&self) -> String {
+        let mut out = String::from("# Managed by worktree-link. Slot\tworktree path.\n");
+        for (path, slot) in &self.slots {
+            out.push_str(&format!("{slot}\t{}\n", path.display()));
+        }
+        out
+    }
+
+    /// Drop entries for worktrees that no longer exist. Returns whether
+    /// anything was removed.
+    fn prune(&mut self, worktrees: &[Worktree]) -> bool {
+        let before = self.slots.len();
+        self.slots
+            .retain(|path, _| worktrees.iter().skip(1).any(|wt| &wt.path == path));
+        self.slots.len() != before
+    }
+
+    /// Return the slot for `target`, assigning the lowest free one if needed.
+    /// Returns the slot and whether the registry changed.
+    fn slot_for(&mut self, target: &Path, worktrees: &[Worktree]) -> (u32, bool) {
+        if worktrees.first().is_some_and(|main| main.path == target) {
+            return (0, false);
+        }
+        if let Some(&slot) = self.slots.get(target) {
+            return (slot, false);
+        }
+        let slot = (1..)
+            .find(|candidate| !self.slots.values().any(|s| s == candidate))
+            .expect("slot space exhausted");
+        self.slots.insert(target.to_path_buf(), slot);
+        (slot, true)
+    }
+}
+
+/// Look up (and if necessary allocate) the slot of `target` in the registry
+/// stored under `common_dir`, releasing slots of worktrees that are gone.
+///
+/// With `persist == false` (dry-run) the slot is computed but not saved.
+/// `worktrees` is `None` if `git worktree list` failed: then nothing is
+/// pruned or saved, since every slot would look released.
+pub fn assign(
+    common_dir: &Path,
+    target: &Path,
+    worktrees: Option<&[Worktree]>,
+    persist: bool,
+) -> Result<u32> {
+    let persist = persist && worktrees.is_some();
+    let _lock = if persist {
+        Some(Lock::acquire(&common_dir.join(LOCK_FILE))?)
+    } else {
+        None
+    };
+
+    let path = common_dir.join(REGISTRY_FILE);
+    let mut registry = match fs::read_to_string(&path) {
+        Ok(content) => Registry::parse(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read registry: {}", path.display()))
+        }
+    };
+
+    let pruned = worktrees.is_some_and(|worktrees| registry.prune(worktrees));
+    let (slot, assigned) = registry.slot_for(target, worktrees.unwrap_or_default());
+    debug!("slot {slot} for {}", target.display());
+
+    if persist && (pruned || assigned) {
+        save(&path, &registry)?;
+    }
+    Ok(slot)
+}
+
+/// An exclusive lock on the lock file, released when the file is closed.
+struct Lock {
+    _file: fs::File,
+}
+
+impl Lock {
+    fn acquire(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock: {}", path.display()))?;
+        let start = Instant::now();
+        loop {
+            match try_lock(&file) {
+                Ok(()) => return Ok(Lock { _file: file }),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to lock: {}", path.display()))
+                }
+            }
+            if start.elapsed() > LOCK_TIMEOUT {
+                bail!(
+                    "Timed out waiting for another run to release the slot registry lock: {}",
+                    path.display()
+                );
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+/// Take an exclusive `flock` without blocking.
+#[cfg(unix)]
+fn try_lock(file: &fs::File) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the descriptor is a valid open file for the duration of the call.
+    let ret = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if ret == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn try_lock(file: &fs::File) -> std::io::Result<()> {
+    file.try_lock().map_err(|e| match e {
+        fs::TryLockError::WouldBlock => std::io::ErrorKind::WouldBlock.into(),
+        fs::TryLockError::Error(e) => e,
+    })
+}
+
+/// Write the registry atomically so concurrent runs never see a partial file.
+fn save(path: &Path, registry: &Registry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp, registry.serialize())
+        .with_context(|| format!("Failed to write registry: {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write registry: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worktree(path: &str) -> Worktree {
+        Worktree {
+            path: PathBuf::from(path),
+            branch: None,
+        }
+    }
+
+    #[test]
+    fn assign_is_stable_and_reuses_released_slots() {
+        let dir = std::env::temp_dir().join("worktree-link-test-registry");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut live = vec![worktree("/r/main"), worktree("/r/a"), worktree("/r/b")];
+        assert_eq!(
+            assign(&dir, Path::new("/r/main"), Some(&live), true).unwrap(),
+            0
+        );
+        assert_eq!(
+            assign(&dir, Path::new("/r/a"), Some(&live), true).unwrap(),
+            1
+        );
+        assert_eq!(
+            assign(&dir, Path::new("/r/b"), Some(&live), true).unwrap(),
+            2
+        );
+        assert_eq!(
+            assign(&dir, Path::new("/r/a"), Some(&live), true).unwrap(),
+            1
+        );
+
+        // Removing `a` releases slot 1 for the next new worktree.
+        live.remove(1);
+        live.push(worktree("/r/c"));
+        assert_eq!(
+            assign(&dir, Path::new("/r/c"), Some(&live), true).unwrap(),
+            1
+        );
+        assert_eq!(
+            assign(&dir, Path::new("/r/b"), Some(&live), true).unwrap(),
+            2
+        );
+
+        // Dry runs compute a slot without reserving it.
+        live.push(worktree("/r/d"));
+        assert_eq!(
+            assign(&dir, Path::new("/r/d"), Some(&live), false).unwrap(),
+            3
+        );
+        let saved = Registry::parse(&fs::read_to_string(dir.join(REGISTRY_FILE)).unwrap());
+        assert!(!saved.slots.contains_key(Path::new("/r/d")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn assign_without_worktree_list_keeps_registry() {
+        let dir = std::env::temp_dir().join("worktree-link-test-registry-unknown");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let live = vec![worktree("/r/main"), worktree("/r/a"), worktree("/r/b")];
+        assign(&dir, Path::new("/r/a"), Some(&live), true).unwrap();
+        assign(&dir, Path::new("/r/b"), Some(&live), true).unwrap();
+        let before = fs::read_to_string(dir.join(REGISTRY_FILE)).unwrap();
+
+        assert_eq!(assign(&dir, Path::new("/r/b"), None, true).unwrap(), 2);
+        assert_eq!(assign(&dir, Path::new("/r/c"), None, true).unwrap(), 3);
+        assert_eq!(fs::read_to_string(dir.join(REGISTRY_FILE)).unwrap(), before);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn assign_hands_out_distinct_slots_concurrently() {
+        let dir = std::env::temp_dir().join("worktree-link-test-registry-lock");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut live = vec![worktree("/r/main")];
+        live.extend((0..8).map(|i| worktree(&format!("/r/{i}"))));
+        let mut slots: Vec<u32> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let (dir, live) = (&dir, &live);
+                    scope.spawn(move || {
+                        let target = PathBuf::from(format!("/r/{i}"));
+                        assign(dir, &target, Some(live), true).unwrap()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        slots.sort();
+        assert_eq!(slots, (1..=8).collect::<Vec<_>>());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn lock_is_released_on_drop_and_ignores_leftover_files() {
+        let dir = std::env::temp_dir().join("worktree-link-test-registry-flock");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(LOCK_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "").unwrap();
+
+        let held = Lock::acquire(&path).unwrap();
+        let other = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        let err = try_lock(&other).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        drop(held);
+        try_lock(&other).unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parse_round_trips_and_skips_garbage() {
+        let registry = Registry::parse("# header\n1\t/r/a\nbogus\nx\t/r/b\n3\t/r/with space\n");
+        assert_eq!(
+            registry.slots,
+            BTreeMap::from([
+                (PathBuf::from("/r/a"), 1),
+                (PathBuf::from("/r/with space"), 3),
+            ])
+        );
+        assert_eq!(Registry::parse(&registry.serialize()), registry);
+    }
+}
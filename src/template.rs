@@ -0,0 +1,177 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::git::Worktree;
+use crate::registry::PORT_STRIDE;
+
+/// Variables available to `template:` entries as `{{name}}` placeholders.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vars(BTreeMap<String, String>);
+
+impl Vars {
+    /// Build the variables describing `target` as a worktree of the repository
+    /// whose main worktree is `source`.
+    ///
+    /// `worktrees` is the output of `git worktree list`; when `target` is not
+    /// listed (e.g. plain directories passed with `--source`), the branch is
+    /// empty and the index falls back to `0`.
+    pub fn for_worktree(source: &Path, target: &Path, worktrees: &[Worktree]) -> Self {
+        let position = worktrees.iter().position(|wt| wt.path == target);
+        let branch = position
+            .and_then(|i| worktrees[i].branch.clone())
+            .unwrap_or_default();
+        let name = target
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut vars = Vars::default();
+        vars.insert("worktree_name", &name);
+        vars.insert("worktree_path", &target.to_string_lossy());
+        vars.insert("worktree_index", &position.unwrap_or(0).to_string());
+        vars.insert("branch", &branch);
+        vars.insert("branch_slug", &slugify(&branch));
+        vars.insert("source_path", &source.to_string_lossy());
+        vars
+    }
+
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.0.insert(name.to_string(), value.to_string());
+    }
+
+    /// Add the registry slot of the worktree and the derived port offset.
+    pub fn insert_slot(&mut self, slot: u32) {
+        self.insert("slot", &slot.to_string());
+        let offset = u64::from(slot) * u64::from(PORT_STRIDE);
+        self.insert("port_offset", &offset.to_string());
+    }
+
+    /// Iterate over all variables in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Replace every `{{ name }}` placeholder in `input`.
+    /// `{{ port:BASE }}` expands to `BASE + port_offset`.
+    /// Unknown variables are an error so typos don't silently produce
+    /// colliding configuration.
+    pub fn render(&self, input: &str) -> Result<String> {
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                bail!("Unterminated `{{{{` in template");
+            };
+            let name = after[..end].trim();
+            if let Some(base) = name.strip_prefix("port:") {
+                output.push_str(&self.port(base.trim())?.to_string());
+            } else {
+                match self.0.get(name) {
+                    Some(value) => output.push_str(value),
+                    None => bail!(
+                        "Unknown template variable `{name}` (available: {})",
+                        self.0.keys().cloned().collect::<Vec<_>>().join(", ")
+                    ),
+                }
+            }
+            rest = &after[end + 2..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    fn port(&self, base: &str) -> Result<u16> {
+        let base: u16 = base
+            .parse()
+            .with_context(|| format!("Invalid port base `{base}` in `port:` placeholder"))?;
+        let offset: u64 = match self.0.get("port_offset") {
+            Some(offset) => offset.parse().context("Invalid port_offset")?,
+            None => bail!("`port:` placeholder requires a worktree slot"),
+        };
+        u16::try_from(offset)
+            .ok()
+            .and_then(|offset| base.checked_add(offset))
+            .with_context(|| format!("port {base} + offset {offset} exceeds 65535"))
+    }
+}
+
+/// Lowercase `value` and replace runs of non-alphanumeric characters with `-`,
+/// e.g. `feature/Login_v2` becomes `feature-login-v2`.
+fn slugify(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn for_worktree_describes_target() {
+        let worktrees = vec![
+            Worktree {
+                path: PathBuf::from("/src/app"),
+                branch: Some("main".into()),
+            },
+            Worktree {
+                path: PathBuf::from("/src/app-login"),
+                branch: Some("feature/Login_v2".into()),
+            },
+        ];
+        let vars = Vars::for_worktree(
+            Path::new("/src/app"),
+            Path::new("/src/app-login"),
+            &worktrees,
+        );
+        let rendered = vars
+            .render(
+                "NAME={{worktree_name}}\nIDX={{ worktree_index }}\nDB=app_{{branch_slug}}\nBRANCH={{branch}}\nSRC={{source_path}}\n",
+            )
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "NAME=app-login\nIDX=1\nDB=app_feature-login-v2\nBRANCH=feature/Login_v2\nSRC=/src/app\n"
+        );
+    }
+
+    #[test]
+    fn render_port_adds_slot_offset() {
+        let mut vars = Vars::default();
+        assert!(vars.render("PORT={{port:3000}}").is_err());
+        vars.insert_slot(2);
+        assert_eq!(
+            vars.render("PORT={{ port:3000 }}\nSLOT={{slot}}\nOFF={{port_offset}}")
+                .unwrap(),
+            "PORT=3020\nSLOT=2\nOFF=20"
+        );
+        assert!(vars.render("{{port:http}}").is_err());
+        assert!(vars.render("{{port:70000}}").is_err());
+        let err = vars.render("{{port:65530}}").unwrap_err();
+        assert!(format!("{err:#}").contains("port 65530 + offset 20 exceeds 65535"));
+        vars.insert_slot(u32::MAX);
+        assert!(vars.render("{{port:3000}}").is_err());
+    }
+
+    #[test]
+    fn render_rejects_unknown_and_unterminated_placeholders() {
+        let vars = Vars::default();
+        assert!(vars
+            .render("PORT={{prot}}")
+            .unwrap_err()
+            .to_string()
+            .contains("prot"));
+        assert!(vars.render("PORT={{port").is_err());
+        assert_eq!(vars.render("no placeholders").unwrap(), "no placeholders");
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::cli::Cli;
+use crate::config;
+use crate::linker::Strategy;
+
+/// Per-user defaults read from `$XDG_CONFIG_HOME/worktree-link/config.toml`.
+///
+/// Most fields mirror a command-line option; options given on the command
+/// line always win, including negations such as `--no-force`. The mode flags
+/// (`--unlink`, `--materialize`), subcommands, and group selection
+/// (`--profile`, `--only`, `--skip`, whose names differ per repository)
+/// cannot be defaulted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct UserConfig {
+    pub source: Option<PathBuf>,
+    pub target: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub dry_run: bool,
+    pub force: bool,
+    pub verbose: bool,
+    pub relative: bool,
+    pub no_ignore: bool,
+    /// Strategy chain (e.g. `"reflink,copy"`) for patterns without a prefix.
+    pub strategy: Option<String>,
+    /// Patterns used for repositories without any `.worktreelinks`.
+    pub patterns: Vec<String>,
+}
+
+impl UserConfig {
+    /// Load the user config file, or the defaults if there is none.
+    pub fn load() -> Result<Self> {
+        match config::user_config_dir() {
+            Some(dir) => Self::from_file(&dir.join("config.toml")),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read user config: {}", path.display()))
+            }
+        };
+        let user: Self = toml::from_str(&content)
+            .with_context(|| format!("Invalid user config: {}", path.display()))?;
+        // Validate eagerly so a typo is reported even if no pattern needs it.
+        user.strategies()
+            .with_context(|| format!("Invalid user config: {}", path.display()))?;
+        Ok(user)
+    }
+
+    /// The default strategy chain for patterns without a prefix, if set.
+    pub fn strategies(&self) -> Result<Option<Vec<Strategy>>> {
+        let Some(chain) = &self.strategy else {
+            return Ok(None);
+        };
+        match Strategy::parse_chain(chain) {
+            Some(strategies) => Ok(Some(strategies)),
+            None => bail!("Unknown strategy: {chain}"),
+        }
+    }
+
+    /// Fill in every option the command line left unset.
+    pub fn apply_to(&self, cli: &mut Cli) {
+        if cli.source.is_none() {
+            cli.source = self.source.clone();
+        }
+        if cli.target.is_none() {
+            cli.target = self.target.clone();
+        }
+        if cli.config.is_empty() {
+            cli.config.extend(self.config.clone());
+        }
+        cli.dry_run |= self.dry_run && !cli.no_dry_run;
+        cli.force |= self.force && !cli.no_force;
+        cli.verbose |= self.verbose && !cli.no_verbose;
+        cli.relative |= self.relative && !cli.no_relative;
+        cli.no_ignore |= self.no_ignore && !cli.respect_ignore;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn apply_to_keeps_command_line_values() {
+        let user: UserConfig = toml::from_str(
+            r#"
+force = true
+no-ignore = true
+config = "/shared/.worktreelinks"
+source = "/default/source"
+strategy = "reflink,copy"
+patterns = [".env", "node_modules"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            user.strategies().unwrap(),
+            Some(vec![Strategy::Reflink, Strategy::Copy])
+        );
+
+        let mut cli = Cli::parse_from(["wtl", "--source", "/explicit"]);
+        user.apply_to(&mut cli);
+        assert!(cli.force);
+        assert!(cli.no_ignore);
+        assert!(!cli.dry_run);
+        assert_eq!(cli.source, Some(PathBuf::from("/explicit")));
+        assert_eq!(cli.config, vec![PathBuf::from("/shared/.worktreelinks")]);
+        assert_eq!(cli.target, None);
+    }
+
+    #[test]
+    fn apply_to_lets_command_line_negations_win() {
+        let user: UserConfig = toml::from_str(
+            "dry-run = true\nforce = true\nverbose = true\nrelative = true\nno-ignore = true\n",
+        )
+        .unwrap();
+
+        let mut cli = Cli::parse_from([
+            "wtl",
+            "--no-dry-run",
+            "--no-force",
+            "--no-verbose",
+            "--no-relative",
+            "--ignore",
+        ]);
+        user.apply_to(&mut cli);
+        assert!(!cli.dry_run);
+        assert!(!cli.force);
+        assert!(!cli.verbose);
+        assert!(!cli.relative);
+        assert!(!cli.no_ignore);
+
+        // The last of a flag and its negation wins.
+        let mut cli = Cli::parse_from([
+            "wtl",
+            "--no-force",
+            "--force",
+            "--relative",
+            "--no-relative",
+        ]);
+        user.apply_to(&mut cli);
+        assert!(cli.force);
+        assert!(!cli.relative);
+    }
+
+    #[test]
+    fn from_file_rejects_unknown_keys_and_strategies() {
+        let dir = std::env::temp_dir().join("worktree-link-test-user-config");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+
+        assert!(UserConfig::from_file(&path).unwrap().patterns.is_empty());
+
+        std::fs::write(&path, "forse = true\n").unwrap();
+        assert!(UserConfig::from_file(&path).is_err());
+
+        std::fs::write(&path, "strategy = \"teleport\"\n").unwrap();
+        let err = format!("{:#}", UserConfig::from_file(&path).unwrap_err());
+        assert!(err.contains("Unknown strategy: teleport"), "{err}");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
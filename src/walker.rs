@@ -1,35 +1,185 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use ignore::overrides::{Override, OverrideBuilder};
 use ignore::{Match, WalkBuilder};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::debug;
 
-/// Build an `Override` matcher from the given patterns.
-pub fn build_overrides(source: &Path, patterns: &[String]) -> Result<Override> {
+use crate::config::{self, Entry};
+use crate::linker::Strategy;
+
+/// A matched path in the source tree and the config entry that selected it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub path: PathBuf,
+    /// The tree `path` was found in: the main source or an `@source`.
+    pub root: PathBuf,
+    /// Index into the entries passed to [`collect_targets`].
+    pub entry: usize,
+}
+
+/// Build an `Override` matcher from the given entries' patterns.
+pub fn build_overrides(source: &Path, entries: &[Entry]) -> Result<Override> {
     let mut builder = OverrideBuilder::new(source);
-    for pattern in patterns {
+    for entry in entries {
         builder
-            .add(pattern)
-            .with_context(|| format!("Invalid pattern: {pattern}"))?;
+            .add(&entry.pattern)
+            .with_context(|| invalid_pattern(entry))?;
     }
     builder.build().with_context(|| "Failed to build overrides")
 }
 
-/// Collect files and directories in `source` that match the given patterns.
+/// Error context for a pattern the glob engine rejected, naming the file and
+/// line it came from when known.
+fn invalid_pattern(entry: &Entry) -> String {
+    match &entry.origin {
+        Some(origin) => format!("Invalid pattern at {origin}: {}", entry.pattern),
+        None => format!("Invalid pattern: {}", entry.pattern),
+    }
+}
+
+/// Resolves a matched path back to the entry whose pattern selected it.
+///
+/// `Override` does not expose which glob matched, so we keep a parallel
+/// `Gitignore` built from the same lines (later entries win, like gitignore).
+/// Each glob records the index of its entry as the file it came from, since
+/// the same pattern may appear in several entries (e.g. with different
+/// destinations).
+#[derive(Clone)]
+pub(crate) struct EntrySelector {
+    matcher: Gitignore,
+}
+
+impl EntrySelector {
+    pub(crate) fn new(source: &Path, entries: &[Entry]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(source);
+        for (i, entry) in entries.iter().enumerate() {
+            builder
+                .add_line(Some(PathBuf::from(i.to_string())), &entry.pattern)
+                .with_context(|| invalid_pattern(entry))?;
+        }
+        let matcher = builder.build().with_context(|| "Failed to build matcher")?;
+        Ok(EntrySelector { matcher })
+    }
+
+    /// Index of the entry that selects `path`, or `None` if it is unmatched
+    /// or excluded by a negation.
+    pub(crate) fn select(&self, path: &Path, is_dir: bool) -> Option<usize> {
+        match self.matcher.matched(path, is_dir) {
+            Match::Ignore(glob) => glob.from()?.to_str()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether a negation (`!pattern`) is the last line matching `path`.
+    pub(crate) fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher.matched(path, is_dir).is_whitelist()
+    }
+
+    /// Like [`Self::is_excluded`] for a file, but also considering the
+    /// lines that match any of its parent directories.
+    pub(crate) fn is_excluded_or_parents(&self, path: &Path) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, false)
+            .is_whitelist()
+    }
+}
+
+/// Whether `path`, matched by `entry`, passes the entry's `when` condition:
+/// the named sibling must exist next to it.
+fn condition_holds(entry: &Entry, path: &Path) -> bool {
+    let Some(when) = &entry.when else {
+        return true;
+    };
+    let holds = path
+        .parent()
+        .is_some_and(|parent| parent.join(when).exists());
+    if !holds {
+        debug!("skipped (no {when} next to it): {}", path.display());
+    }
+    holds
+}
+
+/// Expand a directory matched by a deep entry into its leaf files.
+///
+/// Each file (or symlink) below `dir` becomes its own target so the target
+/// worktree gets real directories with individually linked files. Negations
+/// prune files and subdirectories, and a subdirectory matched by a different
+/// non-deep entry is taken as a whole by that entry.
+fn collect_deep(
+    root: &Path,
+    dir: &Path,
+    entry: usize,
+    entries: &[Entry],
+    selector: &EntrySelector,
+    targets: &mut Vec<Target>,
+) -> Result<()> {
+    let children =
+        fs::read_dir(dir).with_context(|| format!("Failed to read dir: {}", dir.display()))?;
+
+    for child in children {
+        let child = child.with_context(|| "Error walking directory")?;
+        if child.file_name() == ".git" {
+            continue;
+        }
+        let path = child.path();
+        // DirEntry::file_type does not follow symlinks, so symlinked
+        // directories are linked as leaves rather than descended into.
+        let is_dir = child
+            .file_type()
+            .with_context(|| format!("Failed to read file type: {}", path.display()))?
+            .is_dir();
+
+        if selector.is_excluded(&path, is_dir) {
+            debug!("excluded: {}", path.display());
+            continue;
+        }
+
+        let owner = match selector.select(&path, is_dir) {
+            Some(other) if other != entry => other,
+            _ => entry,
+        };
+        if owner != entry && !condition_holds(&entries[owner], &path) {
+            continue;
+        }
+
+        if is_dir && entries[owner].deep {
+            collect_deep(root, &path, owner, entries, selector, targets)?;
+        } else {
+            debug!("matched deep: {}", path.display());
+            targets.push(Target {
+                path,
+                root: root.to_path_buf(),
+                entry: owner,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Collect files and directories in `source` that match the given entries.
 ///
 /// Patterns follow gitignore syntax. When a directory matches, we include it
-/// but do NOT descend into it — it will be symlinked as a whole.
-pub fn collect_targets(
-    source: &Path,
-    patterns: &[String],
-    no_ignore: bool,
-) -> Result<Vec<PathBuf>> {
-    let overrides = build_overrides(source, patterns)?;
+/// but do NOT descend into it — it will be symlinked as a whole. Directories
+/// matched by a deep entry are instead expanded into their leaf files.
+///
+/// Entries with a `when` condition only keep matches whose parent directory
+/// contains the named sibling, e.g. `node_modules when package.json`.
+///
+/// Each target records the entry whose pattern matched it. As with
+/// gitignore, the last matching line wins, so a later `copy:` line can
+/// change the strategy for paths an earlier line already selected.
+pub fn collect_targets(source: &Path, entries: &[Entry], no_ignore: bool) -> Result<Vec<Target>> {
+    let overrides = build_overrides(source, entries)?;
+    let selector = EntrySelector::new(source, entries)?;
     let walker_overrides = overrides.clone();
     let overrides = Arc::new(overrides);
 
-    let mut targets: Vec<PathBuf> = Vec::new();
+    let mut targets: Vec<Target> = Vec::new();
 
     // We use filter_entry to both skip .git and to prune matched directories
     // (avoid descending into them). A matched directory is still yielded as
@@ -89,29 +239,188 @@ pub fn collect_targets(
 
         // Files matched by pattern
         if let Match::Whitelist(_) = overrides.matched(path, is_dir) {
-            debug!("matched: {}", path.display());
-            targets.push(path.to_path_buf());
+            if let Some(entry) = selector
+                .select(path, is_dir)
+                .filter(|&e| condition_holds(&entries[e], path))
+            {
+                debug!("matched: {}", path.display());
+                targets.push(Target {
+                    path: path.to_path_buf(),
+                    root: source.to_path_buf(),
+                    entry,
+                });
+            }
         }
     }
 
-    // Add matched directories that were pruned by filter_entry
+    // Add matched directories that were pruned by filter_entry, expanding
+    // deep entries into their individual files.
     let dirs = matched_dirs.lock().unwrap();
     for dir in dirs.iter() {
-        debug!("matched dir: {}", dir.display());
-        targets.push(dir.clone());
+        if let Some(entry) = selector
+            .select(dir, true)
+            .filter(|&e| condition_holds(&entries[e], dir))
+        {
+            if entries[entry].deep {
+                debug!("matched deep dir: {}", dir.display());
+                collect_deep(source, dir, entry, entries, &selector, &mut targets)?;
+            } else {
+                debug!("matched dir: {}", dir.display());
+                targets.push(Target {
+                    path: dir.clone(),
+                    root: source.to_path_buf(),
+                    entry,
+                });
+            }
+        }
     }
 
     // Sort for deterministic output
-    targets.sort();
-    targets.dedup();
+    targets.sort_by(|a, b| a.path.cmp(&b.path));
+    targets.dedup_by(|a, b| a.path == b.path);
+
+    Ok(targets)
+}
+
+/// Find the `.worktreelinks` (or `.worktreelinks.toml`) files in
+/// subdirectories of `source`, parents before children.
+///
+/// Unlike the link walk, this one respects `.gitignore`: a nested config is
+/// committed alongside the package it describes, so ignored directories such
+/// as `node_modules` never need to be searched.
+pub fn nested_configs(source: &Path) -> Result<Vec<PathBuf>> {
+    let walker = WalkBuilder::new(source)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+
+    let mut found = Vec::new();
+    for entry in walker {
+        let entry = entry.with_context(|| "Error walking directory")?;
+        let path = entry.path();
+        let is_config = entry.file_type().is_some_and(|ft| ft.is_file())
+            && (entry.file_name() == ".worktreelinks"
+                || entry.file_name() == ".worktreelinks.toml");
+        let Some(dir) = path.parent().filter(|&dir| is_config && dir != source) else {
+            continue;
+        };
+        // The TOML file wins over the plain one, as at the top level.
+        if config::default_path(dir) == path {
+            debug!("found nested config: {}", path.display());
+            found.push(path.to_path_buf());
+        }
+    }
+    found.sort_by_key(|path| (path.components().count(), path.clone()));
+    Ok(found)
+}
 
+/// Collect targets from `main` like [`collect_targets`], then from each
+/// declared `@source` for the entries that name it.
+///
+/// The main worktree comes first: a match in an external source is only used
+/// if nothing exists at the same relative path in `main`. Negations without a
+/// source prefix apply to every tree.
+pub fn collect_from_sources(
+    main: &Path,
+    sources: &BTreeMap<String, PathBuf>,
+    entries: &[Entry],
+    no_ignore: bool,
+) -> Result<Vec<Target>> {
+    let mut targets = collect_targets(main, entries, no_ignore)?;
+
+    for (name, root) in sources {
+        // Indices into `entries` of the ones searched in this source.
+        let used: Vec<usize> = (0..entries.len())
+            .filter(|&i| match &entries[i].source {
+                Some(source) => source == name,
+                None => entries[i].pattern.starts_with('!'),
+            })
+            .collect();
+        if !used.iter().any(|&i| entries[i].source.is_some()) {
+            continue;
+        }
+        let subset: Vec<Entry> = used.iter().map(|&i| entries[i].clone()).collect();
+        for mut target in collect_targets(root, &subset, no_ignore)? {
+            let rel = target
+                .path
+                .strip_prefix(root)
+                .with_context(|| "Path is not relative to source")?;
+            let in_main = main.join(rel);
+            if fs::symlink_metadata(&in_main).is_ok() {
+                debug!("using {} instead of {name}", in_main.display());
+                continue;
+            }
+            target.entry = used[target.entry];
+            targets.push(target);
+        }
+    }
     Ok(targets)
 }
 
+/// Where each of `targets` goes, relative to the target worktree.
+///
+/// Fails if two matches would land on the same path, or if one would land
+/// inside another that is linked or copied as a whole (and so would end up
+/// writing into the source). Only `mkdir:` directories may contain others.
+pub fn destinations(targets: &[Target], entries: &[Entry]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::with_capacity(targets.len());
+    let mut owners: HashMap<PathBuf, usize> = HashMap::new();
+    for (i, target) in targets.iter().enumerate() {
+        let rel = target
+            .path
+            .strip_prefix(&target.root)
+            .with_context(|| "Path is not relative to source")?;
+        let path = entries[target.entry].destination_for(rel);
+        if let Some(&other) = owners.get(&path) {
+            bail!(
+                "{} and {} would both be placed at {}",
+                describe(&targets[other], entries),
+                describe(target, entries),
+                path.display()
+            );
+        }
+        owners.insert(path.clone(), i);
+        paths.push(path);
+    }
+
+    for (i, path) in paths.iter().enumerate() {
+        for parent in path.ancestors().skip(1) {
+            let Some(&outer) = owners.get(parent) else {
+                continue;
+            };
+            let is_mkdir = entries[targets[outer].entry]
+                .strategies
+                .iter()
+                .all(|s| matches!(s, Strategy::Mkdir { .. }));
+            if !is_mkdir {
+                bail!(
+                    "{} would be placed at {}, inside {} from {}",
+                    describe(&targets[i], entries),
+                    path.display(),
+                    parent.display(),
+                    describe(&targets[outer], entries)
+                );
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// A matched path and the config line that selected it, for error messages.
+fn describe(target: &Target, entries: &[Entry]) -> String {
+    let rel = target
+        .path
+        .strip_prefix(&target.root)
+        .unwrap_or(&target.path);
+    match &entries[target.entry].origin {
+        Some(origin) => format!("{} ({origin})", rel.display()),
+        None => rel.display().to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
 
     #[test]
     fn collect_targets_skips_unmatched() {
@@ -124,7 +433,7 @@ mod tests {
         let targets = collect_targets(&dir, &[".env".into()], true).unwrap();
         let rel: Vec<_> = targets
             .iter()
-            .map(|p| p.strip_prefix(&dir).unwrap())
+            .map(|t| t.path.strip_prefix(&dir).unwrap())
             .collect();
         assert_eq!(rel, vec![Path::new(".env")]);
     }
@@ -140,7 +449,7 @@ mod tests {
         let targets = collect_targets(&dir, &["node_modules".into()], true).unwrap();
         let rel: Vec<_> = targets
             .iter()
-            .map(|p| p.strip_prefix(&dir).unwrap())
+            .map(|t| t.path.strip_prefix(&dir).unwrap())
             .collect();
         // Should only contain the directory itself, not its children
         assert_eq!(rel, vec![Path::new("node_modules")]);
@@ -161,7 +470,7 @@ mod tests {
         .unwrap();
         let rel: Vec<_> = targets
             .iter()
-            .map(|p| p.strip_prefix(&dir).unwrap())
+            .map(|t| t.path.strip_prefix(&dir).unwrap())
             .collect();
         assert_eq!(rel, vec![Path::new(".env"), Path::new(".env.local")]);
     }
@@ -181,7 +490,7 @@ mod tests {
         let targets = collect_targets(&dir, &["**/*.js".into()], false).unwrap();
         let rel: Vec<_> = targets
             .iter()
-            .map(|p| p.strip_prefix(&dir).unwrap())
+            .map(|t| t.path.strip_prefix(&dir).unwrap())
             .collect();
         assert_eq!(rel, vec![Path::new("src/app.js")]);
     }
@@ -199,7 +508,7 @@ mod tests {
         let targets = collect_targets(&dir, &["**/*.js".into()], true).unwrap();
         let rel: Vec<_> = targets
             .iter()
-            .map(|p| p.strip_prefix(&dir).unwrap())
+            .map(|t| t.path.strip_prefix(&dir).unwrap())
             .collect();
         // Both files should be included regardless of .gitignore
         assert_eq!(
@@ -219,12 +528,220 @@ mod tests {
         let targets = collect_targets(&dir, &[".env".into()], false).unwrap();
         let rel: Vec<_> = targets
             .iter()
-            .map(|p| p.strip_prefix(&dir).unwrap())
+            .map(|t| t.path.strip_prefix(&dir).unwrap())
             .collect();
         // .env should be linked because .worktreelinks override takes precedence
         assert_eq!(rel, vec![Path::new(".env")]);
     }
 
+    #[test]
+    fn collect_targets_records_last_matching_entry() {
+        let dir = tempdir("collect_entry");
+        fs::write(dir.join(".env"), "A=1").unwrap();
+        fs::write(dir.join(".env.local"), "B=2").unwrap();
+
+        let entries: Vec<Entry> = vec![".env*".into(), "copy:.env.local".into()];
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        let found: Vec<_> = targets
+            .iter()
+            .map(|t| (t.path.strip_prefix(&dir).unwrap(), t.entry))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(Path::new(".env"), 0), (Path::new(".env.local"), 1)]
+        );
+    }
+
+    #[test]
+    fn collect_targets_deep_links_leaf_files_honoring_negations() {
+        let dir = tempdir("collect_deep");
+        fs::create_dir_all(dir.join(".vscode/snippets")).unwrap();
+        fs::write(dir.join(".vscode/settings.json"), "{}").unwrap();
+        fs::write(dir.join(".vscode/launch.json"), "{}").unwrap();
+        fs::write(dir.join(".vscode/snippets/rust.json"), "{}").unwrap();
+        fs::create_dir_all(dir.join("node_modules/.cache/babel")).unwrap();
+        fs::create_dir_all(dir.join("node_modules/pkg")).unwrap();
+        fs::write(dir.join("node_modules/.cache/babel/x"), "").unwrap();
+        fs::write(dir.join("node_modules/pkg/index.js"), "").unwrap();
+
+        let entries: Vec<Entry> = vec![
+            "deep:.vscode/".into(),
+            "!.vscode/settings.json".into(),
+            "deep:node_modules".into(),
+            "!node_modules/.cache".into(),
+        ];
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        let found: Vec<_> = targets
+            .iter()
+            .map(|t| (t.path.strip_prefix(&dir).unwrap(), t.entry))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Path::new(".vscode/launch.json"), 0),
+                (Path::new(".vscode/snippets/rust.json"), 0),
+                (Path::new("node_modules/pkg/index.js"), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_targets_deep_hands_subdirectory_to_other_entry() {
+        let dir = tempdir("collect_deep_nested");
+        fs::create_dir_all(dir.join("node_modules/.bin")).unwrap();
+        fs::create_dir_all(dir.join("node_modules/pkg")).unwrap();
+        fs::write(dir.join("node_modules/.bin/tool"), "").unwrap();
+        fs::write(dir.join("node_modules/pkg/index.js"), "").unwrap();
+
+        let entries: Vec<Entry> = vec!["deep:node_modules".into(), "node_modules/.bin".into()];
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        let found: Vec<_> = targets
+            .iter()
+            .map(|t| (t.path.strip_prefix(&dir).unwrap(), t.entry))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Path::new("node_modules/.bin"), 1),
+                (Path::new("node_modules/pkg/index.js"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_targets_checks_when_condition_per_match() {
+        let dir = tempdir("when_condition");
+        for pkg in ["web", "docs"] {
+            fs::create_dir_all(dir.join(format!("packages/{pkg}/node_modules"))).unwrap();
+        }
+        fs::write(dir.join("packages/web/package.json"), "{}").unwrap();
+
+        let targets =
+            collect_targets(&dir, &["**/node_modules when package.json".into()], true).unwrap();
+        let paths: Vec<_> = targets
+            .iter()
+            .map(|t| t.path.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(paths, vec![PathBuf::from("packages/web/node_modules")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn destinations_map_and_detect_collisions() {
+        let dir = tempdir("destinations");
+        fs::create_dir_all(dir.join("shared/fixtures")).unwrap();
+        fs::create_dir_all(dir.join("test")).unwrap();
+        fs::write(dir.join("shared/fixtures/a.json"), "").unwrap();
+        fs::write(dir.join("test/fixtures"), "").unwrap();
+
+        let entries: Vec<Entry> = vec!["shared/fixtures/ => test/fixtures".into()];
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        assert_eq!(
+            destinations(&targets, &entries).unwrap(),
+            vec![PathBuf::from("test/fixtures")]
+        );
+
+        // The mapping collides with the unmapped `test/fixtures`.
+        let entries: Vec<Entry> = vec![
+            "shared/fixtures/ => test/fixtures".into(),
+            "test/fixtures".into(),
+        ];
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        let err = destinations(&targets, &entries).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "shared/fixtures and test/fixtures would both be placed at test/fixtures"
+        );
+
+        // A file mapped into a directory that is linked as a whole.
+        let entries: Vec<Entry> = vec!["shared/ => test".into(), "test/ => test/x".into()];
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        let err = destinations(&targets, &entries).unwrap_err();
+        assert!(err.to_string().contains("inside test from shared"), "{err}");
+
+        // The same pattern twice: the later entry (and its mapping) wins.
+        let entries: Vec<Entry> = vec![
+            "shared/fixtures/a.json => one/a.json".into(),
+            "shared/fixtures/a.json => two/a.json".into(),
+        ];
+        let selector = EntrySelector::new(&dir, &entries).unwrap();
+        assert_eq!(
+            selector.select(&dir.join("shared/fixtures/a.json"), false),
+            Some(1)
+        );
+        let targets = collect_targets(&dir, &entries, true).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(
+            destinations(&targets, &entries).unwrap(),
+            vec![PathBuf::from("two/a.json")]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn collect_from_sources_prefers_main_worktree() {
+        let main = tempdir("sources_main");
+        let shared = tempdir("sources_shared");
+        fs::write(main.join("dev.pem"), "main").unwrap();
+        for name in ["dev.pem", "ca.pem", "old.pem"] {
+            fs::write(shared.join(name), "").unwrap();
+        }
+
+        let sources = BTreeMap::from([("certs".to_string(), shared.clone())]);
+        let entries = vec![
+            Entry {
+                source: Some("certs".into()),
+                .."*.pem".into()
+            },
+            "!old.pem".into(),
+        ];
+        let targets = collect_from_sources(&main, &sources, &entries, true).unwrap();
+        let found: Vec<_> = targets.iter().map(|t| (t.path.clone(), t.entry)).collect();
+        assert_eq!(
+            found,
+            vec![(main.join("dev.pem"), 0), (shared.join("ca.pem"), 0)]
+        );
+        assert_eq!(targets[1].root, shared);
+
+        let _ = fs::remove_dir_all(&main);
+        let _ = fs::remove_dir_all(&shared);
+    }
+
+    #[test]
+    fn nested_configs_lists_parents_first() {
+        let dir = git_tempdir("nested_configs");
+        for sub in [
+            "packages/web",
+            "packages/web/app",
+            "services/api",
+            "ignored",
+        ] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join(".worktreelinks"), "").unwrap();
+        }
+        fs::write(dir.join("services/api/.worktreelinks.toml"), "").unwrap();
+        fs::write(dir.join(".worktreelinks"), "").unwrap();
+        fs::write(dir.join(".gitignore"), "ignored/\n").unwrap();
+
+        let found: Vec<_> = nested_configs(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("packages/web/.worktreelinks"),
+                PathBuf::from("services/api/.worktreelinks.toml"),
+                PathBuf::from("packages/web/app/.worktreelinks"),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
     fn git_tempdir(name: &str) -> PathBuf {
         let dir = std::env::temp_dir().join(format!("worktree-link-test-{name}"));
         let _ = fs::remove_dir_all(&dir);